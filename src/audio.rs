@@ -0,0 +1,39 @@
+// XO-CHIP sound: a 128 bit pattern played back one bit at a time, looping,
+// at a rate set by the pitch register
+pub const PATTERN_SIZE: usize = 16;
+pub const PATTERN_BITS: usize = PATTERN_SIZE * 8;
+
+// Pitch 64 plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioPattern {
+    pub pattern: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl Default for AudioPattern {
+    fn default() -> AudioPattern {
+        AudioPattern::new()
+    }
+}
+
+impl AudioPattern {
+    // A 500 Hz square wave, the buzzer programs hear until they load a pattern
+    pub fn new() -> AudioPattern {
+        AudioPattern {
+            pattern: [0xF0; PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    // Pattern bits played per second
+    pub fn bit_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        let index = index % PATTERN_BITS;
+        (self.pattern[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+}
@@ -0,0 +1,225 @@
+extern crate rust_chip8;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+use rust_chip8::trace::{TraceFormat, TraceRecord};
+
+const DEFAULT_CONTEXT: usize = 5;
+
+const USAGE: &'static str = "Usage: tracecmp [options] <ours> <theirs>
+
+Reads a trace written by --trace and a trace of the same rom from another
+emulator side by side, and stops at the first instruction where the two
+disagree.
+
+Options:
+    --format <template>     Layout of their trace's lines (default: --trace's)
+    --our-format <template> Layout of our trace's lines (default: --trace's)
+    --ignore <fields>       Comma separated fields not to compare:
+                            pc, op, v, i, sp, dt, st, mem
+    --context <n>           Instructions shown before the difference (default 5)
+
+Template fields, values are hex:
+    {pc} {op} {i} {sp} {dt} {st} {v0}-{vf}
+    {v}                     All 16 V registers separated by spaces or commas
+    {maddr} {mval}          A byte of memory after the instruction
+    {cycle}                 A decimal count that is ignored
+    {*}                     Any text up to what follows
+
+Lines that don't fit their template are skipped. A field is only compared
+when both traces have it.";
+
+struct Options {
+    our_path: String,
+    their_path: String,
+    our_format: TraceFormat,
+    their_format: TraceFormat,
+    ignore: Vec<String>,
+    context: usize,
+}
+
+// The lines of a trace that fit its format, with their line numbers
+struct TraceReader {
+    path: String,
+    format: TraceFormat,
+    lines: io::Lines<BufReader<fs::File>>,
+    line_number: usize,
+}
+
+impl TraceReader {
+    fn open(path: &str, format: TraceFormat) -> io::Result<TraceReader> {
+        let file = fs::File::open(path)?;
+        Ok(TraceReader {
+            path: path.to_string(),
+            format: format,
+            lines: BufReader::new(file).lines(),
+            line_number: 0,
+        })
+    }
+
+    fn next_record(&mut self) -> Option<(TraceRecord, String)> {
+        for line in &mut self.lines {
+            self.line_number += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("Could not read {}: {}", self.path, err);
+                    process::exit(2);
+                }
+            };
+            if let Some(record) = self.format.read_line(&line) {
+                return Some((record, line));
+            }
+        }
+        None
+    }
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let readers = TraceReader::open(&options.our_path, options.our_format.clone())
+        .and_then(|ours| TraceReader::open(&options.their_path, options.their_format.clone())
+            .map(|theirs| (ours, theirs)));
+    let (mut ours, mut theirs) = match readers {
+        Ok(readers) => readers,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+
+    // Our line and theirs for the last few instructions
+    let mut context: VecDeque<(String, String)> = VecDeque::new();
+    let mut count = 0;
+
+    loop {
+        let ((our_record, our_line), (their_record, their_line)) = match (ours.next_record(), theirs.next_record()) {
+            (Some(our), Some(their)) => (our, their),
+            (None, None) => break,
+            (Some(_), None) => {
+                println!("{} instructions match, then {} ends", count, theirs.path);
+                return;
+            },
+            (None, Some(_)) => {
+                println!("{} instructions match, then {} ends", count, ours.path);
+                return;
+            },
+        };
+
+        let differences = compare(&our_record, &their_record, &options.ignore);
+        if !differences.is_empty() {
+            println!("Traces diverge at instruction {} (line {} of {}, line {} of {})",
+                     count, ours.line_number, ours.path, theirs.line_number, theirs.path);
+            for difference in differences {
+                println!("    {}", difference);
+            }
+            println!();
+            for (ours, theirs) in &context {
+                println!("ours:   {}", ours);
+                println!("theirs: {}", theirs);
+            }
+            println!("ours:   {}", our_line);
+            println!("theirs: {}", their_line);
+            process::exit(1);
+        }
+
+        count += 1;
+        context.push_back((our_line, their_line));
+        if context.len() > options.context {
+            context.pop_front();
+        }
+    }
+
+    if count == 0 {
+        eprintln!("No line of {} or {} fits its format", ours.path, theirs.path);
+        process::exit(2);
+    }
+    println!("{} instructions match", count);
+}
+
+// What differs between the two records, as descriptions
+fn compare(ours: &TraceRecord, theirs: &TraceRecord, ignore: &[String]) -> Vec<String> {
+    let mut differences = Vec::new();
+    {
+        let mut check = |field: &str, name: &str, ours: Option<u16>, theirs: Option<u16>, digits: usize| {
+            if let (Some(ours), Some(theirs)) = (ours, theirs) {
+                if ours != theirs && !ignore.iter().any(|ignored| ignored == field) {
+                    differences.push(format!("{}: ours {:03$X}, theirs {:03$X}", name, ours, theirs, digits));
+                }
+            }
+        };
+        let byte = |value: Option<u8>| value.map(|value| value as u16);
+        check("pc", "PC", ours.pc, theirs.pc, 4);
+        check("op", "opcode", ours.opcode, theirs.opcode, 4);
+        for x in 0..16 {
+            check("v", &format!("V{:X}", x), byte(ours.regs[x]), byte(theirs.regs[x]), 2);
+        }
+        check("i", "I", ours.i, theirs.i, 4);
+        check("sp", "SP", byte(ours.sp), byte(theirs.sp), 2);
+        check("dt", "DT", byte(ours.dt), byte(theirs.dt), 2);
+        check("st", "ST", byte(ours.st), byte(theirs.st), 2);
+    }
+
+    if !ignore.iter().any(|ignored| ignored == "mem") {
+        match (ours.memory, theirs.memory) {
+            (Some((addr, ours)), Some((their_addr, theirs))) if addr == their_addr && ours != theirs =>
+                differences.push(format!("memory at {:04X}: ours {:02X}, theirs {:02X}", addr, ours, theirs)),
+            (Some((addr, _)), Some((their_addr, _))) if addr != their_addr =>
+                differences.push(format!("memory written: ours {:04X}, theirs {:04X}", addr, their_addr)),
+            _ => {},
+        }
+    }
+    differences
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut our_format = TraceFormat::default();
+    let mut their_format = TraceFormat::default();
+    let mut ignore = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            paths.push(arg);
+            continue;
+        }
+        let value = match args.next() {
+            Some(value) => value,
+            None => return Err(format!("{} expects a value", arg)),
+        };
+        match arg.as_ref() {
+            "--format" => their_format = TraceFormat::parse(&value)?,
+            "--our-format" => our_format = TraceFormat::parse(&value)?,
+            "--ignore" => ignore = value.split(',').map(|field| field.trim().to_string()).collect(),
+            "--context" => context = value.parse()
+                .map_err(|_| format!("--context expects a number, got {}", value))?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("expected our trace and theirs".to_string());
+    }
+    let their_path = paths.pop().unwrap();
+    let our_path = paths.pop().unwrap();
+    Ok(Options {
+        our_path: our_path,
+        their_path: their_path,
+        our_format: our_format,
+        their_format: their_format,
+        ignore: ignore,
+        context: context,
+    })
+}
@@ -0,0 +1,643 @@
+use cpu::{CPU, StepOutcome};
+use fault::CpuFault;
+use instruction::Instruction;
+use quirks::Quirks;
+use variant::Variant;
+use display::Display;
+use interconnect::{self, Interconnect, RomTooLarge};
+use platform::{Platform, HostCommand};
+use headless::HeadlessPlatform;
+use savestate::{self, SaveStateError};
+use rewind::RewindBuffer;
+use rng::{Rng, RngMode};
+use debugger::Debugger;
+use gdbstub::GdbStub;
+use trace::Tracer;
+use profiler::Profiler;
+use coverage::Coverage;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::thread;
+
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+// Roughly 660 instructions per second, a comfortable speed for most games
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 11;
+
+// How long a headless run is allowed to go on for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunLimit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunSummary {
+    pub frames: u64,
+    pub instructions: u64,
+
+    // True if the program halted, faulted or the user quit before the limit
+    // was reached
+    pub halted: bool,
+
+    pub fault: Option<CpuFault>,
+}
+
+// A complete Chip 8 machine: the CPU together with the RAM and framebuffer
+// owned by its interconnect.
+pub struct Chip8 {
+    cpu: CPU,
+
+    instructions_per_frame: u32,
+
+    // Set when the CPU faults, execution stops until the next load_rom
+    fault: Option<CpuFault>,
+
+    // The ROM's path, which save state slot files are named after
+    save_path: Option<PathBuf>,
+
+    // Snapshots of recent frames, recorded while rewind is enabled
+    rewind: Option<RewindBuffer>,
+
+    // Set while the user holds the rewind key
+    rewinding: bool,
+
+    debugger: Option<Debugger>,
+
+    gdb: Option<GdbStub>,
+
+    tracer: Option<Tracer>,
+
+    // Why tracing stopped early, if writing the trace failed
+    trace_error: Option<io::Error>,
+    coverage: Option<Coverage>,
+
+    // Size of the loaded program, the region coverage is kept for
+    program_len: usize,
+}
+
+impl Chip8 {
+    // A program too big for memory is cut short, use load_rom to have it
+    // rejected instead
+    pub fn new(program: Vec<u8>) -> Chip8 {
+        let program_len = cmp::min(program.len(), interconnect::CLASSIC_RAM_SIZE - interconnect::PROGRAM_START);
+        let interconnect = Interconnect::new(program);
+        Chip8 {
+            cpu: CPU::new(interconnect),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            fault: None,
+            save_path: None,
+            rewind: None,
+            rewinding: false,
+            debugger: None,
+            gdb: None,
+            tracer: None,
+            trace_error: None,
+            coverage: None,
+            program_len: program_len,
+        }
+    }
+
+    // Replace the loaded program and reset the machine to its power on state.
+    // The machine is left as it was if the program doesn't fit in memory.
+    pub fn load_rom(&mut self, program: &[u8]) -> Result<(), RomTooLarge> {
+        self.cpu.interconnect_mut().load_program(program)?;
+        self.cpu.reset();
+        self.fault = None;
+        self.program_len = program.len();
+        if self.coverage.is_some() {
+            self.start_coverage();
+        }
+        Ok(())
+    }
+
+    // Execute a single instruction. Timers are left alone, see tick_timers.
+    // A fault is remembered the same way as when running whole frames.
+    pub fn step(&mut self) -> StepOutcome {
+        let outcome = self.cpu.step();
+        if let StepOutcome::Faulted(fault) = outcome {
+            self.fault = Some(fault);
+        }
+        outcome
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.cpu.tick_timers();
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.cpu.variant()
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.cpu.set_variant(variant);
+    }
+
+    pub fn rng_mode(&self) -> RngMode {
+        self.cpu.interconnect().rng().mode()
+    }
+
+    // Switch how Cxkk picks numbers, keeping the current seed
+    pub fn set_rng_mode(&mut self, mode: RngMode) {
+        let state = self.cpu.interconnect().rng().state();
+        self.cpu.interconnect_mut().set_rng(Rng::with_mode(mode, state));
+    }
+
+    // Make Cxkk produce the same sequence every time the seed is reused
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        let mode = self.rng_mode();
+        self.cpu.interconnect_mut().set_rng(Rng::with_mode(mode, seed));
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    // Set how many instructions run between 60 Hz timer ticks, which sets the
+    // CPU speed independently of the timers
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    // Write a snapshot of the whole machine: registers, stack, timers, RAM,
+    // framebuffer, keys, audio and RNG state
+    pub fn save_state<W: Write>(&self, writer: &mut W) -> Result<(), SaveStateError> {
+        writer.write_all(savestate::MAGIC)?;
+        writer.write_u16::<BigEndian>(savestate::VERSION)?;
+        self.cpu.write_state(writer)
+    }
+
+    // Restore a snapshot written by save_state. The machine is left as it was
+    // if the snapshot is rejected. Keys held on the host stay held, and a
+    // request to quit is not forgotten.
+    pub fn load_state<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveStateError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != savestate::MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.read_u16::<BigEndian>()?;
+        if version != savestate::VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let mut cpu = CPU::new(Interconnect::new(Vec::new()));
+        cpu.read_state(reader)?;
+        cpu.interconnect_mut().set_key_state(self.cpu.interconnect().key_state());
+        cpu.interconnect_mut().halt = self.cpu.interconnect().halt;
+        cpu.interconnect_mut().set_access_logging(self.cpu.interconnect().is_access_logging());
+        cpu.set_profiler(self.cpu.take_profiler());
+        self.cpu = cpu;
+        self.fault = None;
+        Ok(())
+    }
+
+    pub fn save_state_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveStateError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save_state(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load_state_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SaveStateError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        self.load_state(&mut reader)
+    }
+
+    // Enable the numbered save state slots, stored next to the ROM
+    pub fn set_save_path<P: Into<PathBuf>>(&mut self, rom_path: P) {
+        self.save_path = Some(rom_path.into());
+    }
+
+    pub fn save_slot(&self, slot: u8) -> Result<PathBuf, SaveStateError> {
+        let path = self.slot_path(slot)?;
+        self.save_state_to_file(&path)?;
+        Ok(path)
+    }
+
+    pub fn load_slot(&mut self, slot: u8) -> Result<PathBuf, SaveStateError> {
+        let path = self.slot_path(slot)?;
+        self.load_state_from_file(&path)?;
+        Ok(path)
+    }
+
+    fn slot_path(&self, slot: u8) -> Result<PathBuf, SaveStateError> {
+        match self.save_path {
+            Some(ref rom_path) => Ok(savestate::slot_path(rom_path, slot)),
+            None => Err(SaveStateError::NoSavePath),
+        }
+    }
+
+    // Record a snapshot after every frame so up to frames of them can be
+    // stepped back through. 0 turns rewind off.
+    pub fn set_rewind_frames(&mut self, frames: usize) {
+        self.rewind = if frames > 0 { Some(RewindBuffer::new(frames)) } else { None };
+    }
+
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    fn record_rewind_frame(&mut self) {
+        if self.rewind.is_none() {
+            return;
+        }
+        let mut snapshot = Vec::new();
+        if self.save_state(&mut snapshot).is_ok() {
+            if let Some(ref mut rewind) = self.rewind {
+                rewind.push(snapshot);
+            }
+        }
+    }
+
+    // Go back one frame and show it instead of running the next one. Returns
+    // false when there is nothing older to go back to.
+    pub fn rewind_frame<P: Platform>(&mut self, platform: &mut P) -> bool {
+        self.cpu.interconnect_mut().handle_events(platform);
+
+        let snapshot = match self.rewind {
+            Some(ref mut rewind) => rewind.pop(),
+            None => None,
+        };
+        let rewound = match snapshot {
+            Some(snapshot) => self.load_state(&mut &snapshot[..]).is_ok(),
+            None => false,
+        };
+
+        platform.stop_beep();
+        self.cpu.interconnect_mut().update_audio(platform);
+        self.cpu.interconnect_mut().present(platform);
+        rewound
+    }
+
+    // Stop before the next instruction and hand control to the debugger
+    pub fn attach_debugger(&mut self, mut debugger: Debugger) {
+        debugger.pause(&self.cpu, "Paused");
+        self.debugger = Some(debugger);
+        self.update_access_logging();
+    }
+
+    pub fn detach_debugger(&mut self) -> Option<Debugger> {
+        let debugger = self.debugger.take();
+        self.update_access_logging();
+        debugger
+    }
+
+    pub fn debugger(&self) -> Option<&Debugger> {
+        self.debugger.as_ref()
+    }
+
+    // Serve the GDB remote protocol, holding execution until a client
+    // connects and resumes it
+    pub fn attach_gdb(&mut self, gdb: GdbStub) {
+        self.gdb = Some(gdb);
+        self.update_access_logging();
+    }
+
+    pub fn detach_gdb(&mut self) -> Option<GdbStub> {
+        let gdb = self.gdb.take();
+        self.update_access_logging();
+        gdb
+    }
+
+    pub fn gdb(&self) -> Option<&GdbStub> {
+        self.gdb.as_ref()
+    }
+
+    // Count where instructions are executed from now on, see Profiler
+    pub fn start_profiling(&mut self) {
+        self.cpu.set_profiler(Some(Profiler::new()));
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler()
+    }
+
+    // Record which bytes of the program are executed or used as data from
+    // now on, see Coverage
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(interconnect::PROGRAM_START, self.program_len));
+        self.update_access_logging();
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Log every instruction executed from now on
+    pub fn start_trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn stop_trace(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // The write error that ended tracing, if it ended that way
+    pub fn take_trace_error(&mut self) -> Option<io::Error> {
+        self.trace_error.take()
+    }
+
+    fn trace(&mut self) {
+        let result = match self.tracer {
+            Some(ref mut tracer) => tracer.trace(&self.cpu),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.trace_error = Some(err);
+            self.tracer = None;
+        }
+    }
+
+    // Tracing goes on until the machine is dropped, which may be at exit, so
+    // every frame's lines are written out as it ends
+    fn flush_trace(&mut self) {
+        let result = match self.tracer {
+            Some(ref mut tracer) => tracer.flush(),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.trace_error = Some(err);
+            self.tracer = None;
+        }
+    }
+
+    // Watchpoints and coverage need to see the memory each instruction touches
+    fn update_access_logging(&mut self) {
+        let enabled = self.debugger.is_some() || self.gdb.is_some() || self.coverage.is_some();
+        self.cpu.interconnect_mut().set_access_logging(enabled);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.debugger.as_ref().is_some_and(|debugger| debugger.is_paused()) ||
+            self.gdb.as_ref().is_some_and(|gdb| gdb.is_paused())
+    }
+
+    fn process_debugger_commands(&mut self) {
+        let mut quit = match self.debugger {
+            Some(ref mut debugger) => debugger.process_commands(&self.cpu),
+            None => false,
+        };
+        if let Some(ref mut gdb) = self.gdb {
+            quit |= gdb.process(&mut self.cpu);
+        }
+        if quit {
+            self.cpu.interconnect_mut().halt = true;
+        }
+    }
+
+    // Carry out a request the user made through the frontend, returning the
+    // save state file it used if it was a save or load
+    pub fn handle_command(&mut self, command: HostCommand) -> Result<Option<PathBuf>, SaveStateError> {
+        match command {
+            HostCommand::SaveState(slot) => self.save_slot(slot).map(Some),
+            HostCommand::LoadState(slot) => self.load_slot(slot).map(Some),
+            HostCommand::Rewind(held) => {
+                self.rewinding = held;
+                Ok(None)
+            },
+            HostCommand::Break => {
+                match self.debugger {
+                    Some(ref mut debugger) => debugger.pause(&self.cpu, "Interrupted"),
+                    None => self.attach_debugger(Debugger::from_stdin()),
+                }
+                Ok(None)
+            },
+        }
+    }
+
+    // True once the program has ended or faulted, or the user asked to quit
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted() || self.cpu.interconnect().halt || self.fault.is_some()
+    }
+
+    pub fn fault(&self) -> Option<CpuFault> {
+        self.fault
+    }
+
+    // Run in real time until the program halts or the user quits. A paused
+    // debugger keeps the machine around after a halt or fault so it can be
+    // inspected, until it is continued or told to quit. Frames are
+    // paced against a monotonic clock so the timers tick at a true 60 Hz no
+    // matter how long the instructions in between take.
+    pub fn run<P: Platform>(&mut self, platform: &mut P) {
+        let frame_duration = Duration::new(0, 1_000_000_000 / FRAMES_PER_SECOND);
+        let mut next_frame = Instant::now();
+
+        while (!self.is_halted() || self.is_paused()) && !self.cpu.interconnect().halt {
+            if self.rewinding {
+                self.rewind_frame(platform);
+            } else {
+                self.run_frame(platform);
+            }
+            for command in platform.take_commands() {
+                let result = self.handle_command(command);
+                platform.command_finished(command, result);
+            }
+
+            next_frame += frame_duration;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else if now - next_frame > frame_duration * 4 {
+                // Fell far behind (e.g. the window was being dragged), don't
+                // try to make up for it by racing through frames
+                next_frame = now;
+            }
+        }
+    }
+
+    // Emulate one 60 Hz frame: poll input, execute instructions_per_frame
+    // instructions, tick the timers and present the display. Returns the
+    // number of instructions executed.
+    pub fn run_frame<P: Platform>(&mut self, platform: &mut P) -> u32 {
+        let budget = self.instructions_per_frame;
+        self.run_frame_with_budget(platform, budget)
+    }
+
+    fn run_frame_with_budget<P: Platform>(&mut self, platform: &mut P, budget: u32) -> u32 {
+        self.cpu.interconnect_mut().handle_events(platform);
+        self.process_debugger_commands();
+
+        let mut executed = 0;
+        while executed < budget && !self.is_halted() {
+            if self.is_paused() {
+                break;
+            }
+            if let Some(ref mut debugger) = self.debugger {
+                if debugger.before_step(&self.cpu) {
+                    break;
+                }
+            }
+            if let Some(ref mut gdb) = self.gdb {
+                if gdb.before_step(&self.cpu) {
+                    break;
+                }
+            }
+
+            self.trace();
+            let pc = self.cpu.reg_pc();
+            let outcome = self.step();
+            let accesses = self.cpu.interconnect_mut().take_accesses();
+            if let Some(ref mut debugger) = self.debugger {
+                debugger.after_step(&self.cpu, pc, outcome, &accesses);
+            }
+            if let Some(ref mut gdb) = self.gdb {
+                gdb.after_step(outcome, &accesses);
+            }
+            if let Some(ref mut coverage) = self.coverage {
+                let len = match outcome {
+                    StepOutcome::Executed(instruction) | StepOutcome::Drew(instruction) => instruction.size(),
+                    StepOutcome::Faulted(_) => 0,
+                    _ => 2,
+                };
+                coverage.mark_executed(pc as usize, len as usize);
+                for access in &accesses {
+                    coverage.mark_access(access);
+                }
+            }
+
+            match outcome {
+                StepOutcome::Halted => break,
+                StepOutcome::WaitingForKey => {
+                    // The keys won't change before the next frame's poll, so
+                    // Fx0A has nothing new to see until then
+                    executed += 1;
+                    break;
+                },
+                StepOutcome::Drew(Instruction::Drw(..)) if self.cpu.quirks().display_wait => {
+                    // The sprite is drawn, the rest of the frame is spent
+                    // waiting for the vertical blank
+                    executed += 1;
+                    break;
+                },
+                _ => {}
+            }
+            executed += 1;
+        }
+
+        self.flush_trace();
+
+        // Time stands still while the debugger has the machine stopped
+        if executed == 0 && self.is_paused() {
+            platform.stop_beep();
+            self.cpu.interconnect_mut().present(platform);
+            return 0;
+        }
+
+        self.cpu.tick_timers();
+        self.cpu.interconnect_mut().update_audio(platform);
+        if self.cpu.reg_st() > 0 {
+            platform.start_beep();
+        } else {
+            platform.stop_beep();
+        }
+        self.cpu.interconnect_mut().present(platform);
+        self.record_rewind_frame();
+
+        executed
+    }
+
+    // Run without a window or audio device as fast as possible until the limit
+    // is reached or the program halts
+    pub fn run_headless(&mut self, limit: RunLimit) -> RunSummary {
+        let mut platform = HeadlessPlatform::new();
+        self.run_for(&mut platform, limit)
+    }
+
+    // Run frames on the given platform back to back without any pacing
+    pub fn run_for<P: Platform>(&mut self, platform: &mut P, limit: RunLimit) -> RunSummary {
+        let mut summary = RunSummary {
+            frames: 0,
+            instructions: 0,
+            halted: false,
+            fault: None,
+        };
+
+        loop {
+            let budget = match limit {
+                RunLimit::Frames(frames) => {
+                    if summary.frames >= frames {
+                        break;
+                    }
+                    self.instructions_per_frame
+                },
+                RunLimit::Instructions(instructions) => {
+                    if summary.instructions >= instructions {
+                        break;
+                    }
+                    let remaining = instructions - summary.instructions;
+                    if remaining < self.instructions_per_frame as u64 {
+                        remaining as u32
+                    } else {
+                        self.instructions_per_frame
+                    }
+                },
+            };
+
+            if self.is_halted() {
+                break;
+            }
+
+            summary.instructions += self.run_frame_with_budget(platform, budget) as u64;
+            summary.frames += 1;
+        }
+
+        summary.halted = self.is_halted();
+        summary.fault = self.fault;
+        summary
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        self.cpu.reg_gpr()
+    }
+
+    pub fn reg_i(&self) -> u16 {
+        self.cpu.reg_i()
+    }
+
+    pub fn reg_pc(&self) -> u16 {
+        self.cpu.reg_pc()
+    }
+
+    pub fn reg_sp(&self) -> u8 {
+        self.cpu.reg_sp()
+    }
+
+    pub fn reg_dt(&self) -> u8 {
+        self.cpu.reg_dt()
+    }
+
+    pub fn reg_st(&self) -> u8 {
+        self.cpu.reg_st()
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        self.cpu.stack()
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        self.cpu.interconnect().ram()
+    }
+
+    pub fn display_state(&self) -> &Display {
+        self.cpu.interconnect().display_state()
+    }
+}
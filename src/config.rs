@@ -0,0 +1,101 @@
+use std::fs;
+
+// Settings read from an INI style file:
+//
+//     ; comment
+//     [section]
+//     key = value
+//
+// Sections and keys are case insensitive. Entries keep the order they were
+// written in, and a key may appear more than once.
+pub struct Config {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config { sections: Vec::new() }
+    }
+
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Config::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(format!("line {}: section name is missing its ]", number + 1));
+                }
+                let name = line[1..line.len() - 1].trim().to_lowercase();
+                config.sections.push((name, Vec::new()));
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim().to_lowercase();
+            let value = match parts.next() {
+                Some(value) => value.trim().to_string(),
+                None => return Err(format!("line {}: expected key = value", number + 1)),
+            };
+            match config.sections.last_mut() {
+                Some(&mut (_, ref mut entries)) => entries.push((key, value)),
+                None => return Err(format!("line {}: {} is outside any section", number + 1, key)),
+            }
+        }
+        Ok(config)
+    }
+
+    // Every entry of the section, in order. A section written more than once
+    // counts as one.
+    pub fn section(&self, name: &str) -> Vec<(&str, &str)> {
+        let name = name.to_lowercase();
+        self.sections.iter()
+            .filter(|(section, _)| *section == name)
+            .flat_map(|(_, entries)| entries.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    // The last value given for key in the section
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        let key = key.to_lowercase();
+        self.section(section).into_iter()
+            .rev()
+            .find(|&(entry, _)| entry == key)
+            .map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn sections_and_keys() {
+        let config = Config::parse("; comment\n[Keys]\npreset = hex\n\n[other]\nx = 1\n[keys]\nPreset = qwerty\n").unwrap();
+        assert_eq!(config.section("keys"), vec![("preset", "hex"), ("preset", "qwerty")]);
+        assert_eq!(config.get("KEYS", "preset"), Some("qwerty"));
+        assert_eq!(config.get("other", "x"), Some("1"));
+    }
+
+    #[test]
+    fn missing_section_is_empty() {
+        let config = Config::parse("[keys]\npreset = hex\n").unwrap();
+        assert!(config.section("display").is_empty());
+        assert_eq!(config.get("display", "scale"), None);
+        assert_eq!(config.get("keys", "5"), None);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert_eq!(Config::parse("[keys\n").err().unwrap(), "line 1: section name is missing its ]");
+        assert_eq!(Config::parse("[keys]\n\npreset\n").err().unwrap(), "line 3: expected key = value");
+        assert_eq!(Config::parse("preset = hex\n").err().unwrap(), "line 1: preset is outside any section");
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use sdl2::controller::{Axis, Button};
+
+use config::Config;
+
+// How far a stick has to move from the centre before it presses anything,
+// out of 32767
+pub const DEFAULT_DEADZONE: i16 = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Negative,
+    Positive,
+}
+
+// Which controller buttons and stick directions press which of the 16
+// CHIP-8 keys. Every connected controller uses the same mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerMap {
+    buttons: HashMap<Button, u8>,
+    axes: HashMap<(Axis, Direction), u8>,
+    deadzone: i16,
+}
+
+impl ControllerMap {
+    // The D-pad and left stick on 2 4 6 8, which most games move with, and
+    // the face buttons on 5 0 1 3
+    pub fn standard() -> ControllerMap {
+        let buttons = [
+            (Button::DPadUp, 0x2),
+            (Button::DPadLeft, 0x4),
+            (Button::DPadRight, 0x6),
+            (Button::DPadDown, 0x8),
+            (Button::A, 0x5),
+            (Button::B, 0x0),
+            (Button::X, 0x1),
+            (Button::Y, 0x3),
+        ];
+        let axes = [
+            ((Axis::LeftY, Direction::Negative), 0x2),
+            ((Axis::LeftX, Direction::Negative), 0x4),
+            ((Axis::LeftX, Direction::Positive), 0x6),
+            ((Axis::LeftY, Direction::Positive), 0x8),
+        ];
+        ControllerMap {
+            buttons: buttons.iter().cloned().collect(),
+            axes: axes.iter().cloned().collect(),
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+
+    // Read the [controller] section, then the [controller.<rom>] section for
+    // the rom being played, named after its file without the extension. Each
+    // maps CHIP-8 keys to a comma separated list of buttons and stick
+    // directions, named as in SDL's controller mappings:
+    //
+    //     [controller]
+    //     deadzone = 8000
+    //     5 = a, rightshoulder
+    //     2 = dpup, -lefty
+    //
+    //     [controller.brix]
+    //     5 =
+    //     4 = dpleft, -leftx
+    //
+    // A key given in a section replaces what it was mapped to before, an
+    // empty list leaves it unmapped. Within a section an input can only be
+    // given to one CHIP-8 key.
+    pub fn from_config(config: &Config, rom_name: &str) -> Result<ControllerMap, String> {
+        let mut map = ControllerMap::standard();
+        map.apply(config, "controller")?;
+        map.apply(config, &format!("controller.{}", rom_name))?;
+        Ok(map)
+    }
+
+    fn apply(&mut self, config: &Config, section: &str) -> Result<(), String> {
+        let mut remapped = [false; 16];
+        let mut bound = HashMap::new();
+        for (name, value) in config.section(section) {
+            if name == "deadzone" {
+                self.deadzone = match value.parse() {
+                    Ok(deadzone) if deadzone >= 0 => deadzone,
+                    _ => return Err(format!("[{}] deadzone expects 0-32767, got {}", section, value)),
+                };
+                continue;
+            }
+            let key = match u8::from_str_radix(name, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("[{}] {} is not a CHIP-8 key, expected 0-F", section, name)),
+            };
+            if !remapped[key as usize] {
+                self.buttons.retain(|_, mapped| *mapped != key);
+                self.axes.retain(|_, mapped| *mapped != key);
+                remapped[key as usize] = true;
+            }
+            for name in value.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                let input = parse_input(name)
+                    .ok_or_else(|| format!("[{}] unknown controller input {}", section, name))?;
+                if let Some(other) = bound.insert(input, key) {
+                    if other != key {
+                        return Err(format!("[{}] {} is bound to both {:X} and {:X}", section, name, other, key));
+                    }
+                }
+                match input {
+                    ControllerInput::Button(button) => {
+                        self.buttons.insert(button, key);
+                    },
+                    ControllerInput::Axis(axis, direction) => {
+                        self.axes.insert((axis, direction), key);
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn button(&self, button: Button) -> Option<u8> {
+        self.buttons.get(&button).cloned()
+    }
+
+    pub fn axis(&self, axis: Axis, direction: Direction) -> Option<u8> {
+        self.axes.get(&(axis, direction)).cloned()
+    }
+
+    // Which way, if any, an axis at value is pushed past the deadzone
+    pub fn direction(&self, value: i16) -> Option<Direction> {
+        if value > self.deadzone {
+            Some(Direction::Positive)
+        } else if value < -self.deadzone {
+            Some(Direction::Negative)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ControllerMap {
+    fn default() -> ControllerMap {
+        ControllerMap::standard()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ControllerInput {
+    Button(Button),
+    Axis(Axis, Direction),
+}
+
+// A button name, or an axis name with + or - for the direction. Triggers
+// only move one way, so they can be given without one.
+fn parse_input(name: &str) -> Option<ControllerInput> {
+    if name.starts_with('+') || name.starts_with('-') {
+        let direction = if name.starts_with('+') { Direction::Positive } else { Direction::Negative };
+        return Axis::from_string(&name[1..]).map(|axis| ControllerInput::Axis(axis, direction));
+    }
+    match Button::from_string(name) {
+        Some(button) => Some(ControllerInput::Button(button)),
+        None => match Axis::from_string(name) {
+            Some(axis @ Axis::TriggerLeft) | Some(axis @ Axis::TriggerRight) => {
+                Some(ControllerInput::Axis(axis, Direction::Positive))
+            },
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::controller::{Axis, Button};
+
+    use config::Config;
+    use super::{ControllerMap, Direction};
+
+    fn from_config(text: &str, rom_name: &str) -> Result<ControllerMap, String> {
+        ControllerMap::from_config(&Config::parse(text).unwrap(), rom_name)
+    }
+
+    #[test]
+    fn missing_sections_are_standard() {
+        assert_eq!(from_config("[keys]\npreset = hex\n", "brix").unwrap(), ControllerMap::standard());
+    }
+
+    #[test]
+    fn rom_section_overrides_the_general_one() {
+        let text = "[controller]\n5 = rightshoulder\n[controller.brix]\n4 = -rightx\n5 =\n";
+        let map = from_config(text, "brix").unwrap();
+        assert_eq!(map.button(Button::RightShoulder), None);
+        assert_eq!(map.button(Button::A), None);
+        assert_eq!(map.axis(Axis::RightX, Direction::Negative), Some(0x4));
+        assert_eq!(map.axis(Axis::LeftX, Direction::Negative), None);
+
+        let map = from_config(text, "pong").unwrap();
+        assert_eq!(map.button(Button::RightShoulder), Some(0x5));
+        assert_eq!(map.axis(Axis::LeftX, Direction::Negative), Some(0x4));
+    }
+
+    #[test]
+    fn deadzone() {
+        let map = from_config("[controller]\ndeadzone = 100\n", "brix").unwrap();
+        assert_eq!(map.direction(100), None);
+        assert_eq!(map.direction(101), Some(Direction::Positive));
+        assert_eq!(map.direction(-101), Some(Direction::Negative));
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_eq!(from_config("[controller]\n5 = turbo\n", "brix").err().unwrap(),
+                   "[controller] unknown controller input turbo");
+        assert_eq!(from_config("[controller]\n5 = +dpup\n", "brix").err().unwrap(),
+                   "[controller] unknown controller input +dpup");
+        assert_eq!(from_config("[controller.brix]\nG = a\n", "brix").err().unwrap(),
+                   "[controller.brix] G is not a CHIP-8 key, expected 0-F");
+        assert_eq!(from_config("[controller]\ndeadzone = -1\n", "brix").err().unwrap(),
+                   "[controller] deadzone expects 0-32767, got -1");
+    }
+
+    #[test]
+    fn duplicate_bindings_are_rejected() {
+        assert_eq!(from_config("[controller]\n5 = a\n6 = a\n", "brix").err().unwrap(),
+                   "[controller] a is bound to both 5 and 6");
+        assert!(from_config("[controller]\n5 = a\n[controller.brix]\n6 = a\n", "brix").is_ok());
+    }
+}
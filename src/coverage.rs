@@ -0,0 +1,140 @@
+use std::cmp;
+use std::io::{self, Write};
+
+use disasm::DisasmLine;
+use interconnect::{AccessKind, MemoryAccess};
+
+const EXECUTED: u8 = 0b001;
+const READ: u8 = 0b010;
+const WRITTEN: u8 = 0b100;
+
+// Which bytes of the program were executed as instructions, read or written
+// as data, or never touched
+pub struct Coverage {
+    start: usize,
+
+    // EXECUTED, READ and WRITTEN flags for each byte from start
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    // Cover the len bytes from start, anything outside them is ignored
+    pub fn new(start: usize, len: usize) -> Coverage {
+        Coverage {
+            start: start,
+            flags: vec![0; len],
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.flags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    // An instruction len bytes long ran from addr
+    pub fn mark_executed(&mut self, addr: usize, len: usize) {
+        self.mark(addr, len, EXECUTED);
+    }
+
+    pub fn mark_access(&mut self, access: &MemoryAccess) {
+        let flag = if access.kind == AccessKind::Read { READ } else { WRITTEN };
+        self.mark(access.addr, access.len, flag);
+    }
+
+    fn mark(&mut self, addr: usize, len: usize, flag: u8) {
+        for addr in addr..addr + len {
+            if addr >= self.start && addr - self.start < self.flags.len() {
+                self.flags[addr - self.start] |= flag;
+            }
+        }
+    }
+
+    fn flags(&self, addr: usize) -> u8 {
+        if addr < self.start {
+            return 0;
+        }
+        self.flags.get(addr - self.start).cloned().unwrap_or(0)
+    }
+
+    pub fn is_executed(&self, addr: usize) -> bool {
+        self.flags(addr) & EXECUTED != 0
+    }
+
+    pub fn is_read(&self, addr: usize) -> bool {
+        self.flags(addr) & READ != 0
+    }
+
+    pub fn is_written(&self, addr: usize) -> bool {
+        self.flags(addr) & WRITTEN != 0
+    }
+
+    // Bytes executed, read as data and never touched
+    pub fn summary(&self) -> (usize, usize, usize) {
+        let count = |flag: u8| self.flags.iter().filter(|&&flags| flags & flag != 0).count();
+        let untouched = self.flags.iter().filter(|&&flags| flags == 0).count();
+        (count(EXECUTED), count(READ), untouched)
+    }
+
+    // The program as an annotated disassembly. Executed bytes are shown as
+    // instructions, other touched bytes as data and untouched runs as a
+    // single line, each marked with what happened to it:
+    //
+    //     0200  X--  6005       LD V0, 05
+    //     0240  -R-  F0         DB F0
+    //     0250  ---             12 bytes never touched
+    pub fn write_annotated<W: Write>(&self, writer: &mut W, ram: &[u8]) -> io::Result<()> {
+        // A rom can be bigger than the variant's memory, which is all that
+        // can be shown
+        let end = cmp::min(self.start + self.flags.len(), ram.len());
+        let (executed, read, untouched) = self.summary();
+        writeln!(writer, "; Coverage of {:04X}-{:04X}: {} bytes executed, {} read as data, {} never touched",
+                 self.start, end.saturating_sub(1), executed, read, untouched)?;
+        writeln!(writer, "; X executed, R read, W written")?;
+        writeln!(writer)?;
+
+        let mut addr = self.start;
+        while addr < end {
+            let flags = self.flags(addr);
+            if flags & EXECUTED != 0 {
+                if let Some(line) = DisasmLine::at(ram, addr) {
+                    let len = cmp::min(line.size(), end - addr);
+                    let flags = (addr..addr + len).fold(0, |all, addr| all | self.flags(addr));
+                    let text = match line.instruction {
+                        Some(instruction) if line.size() == 4 => format!("{:04X} {:04X}  {}", line.opcode, line.next, instruction),
+                        Some(instruction) => format!("{:04X}       {}", line.opcode, instruction),
+                        None => format!("{:04X}       DW {:04X}", line.opcode, line.opcode),
+                    };
+                    writeln!(writer, "{:04X}  {}  {}", addr, flag_chars(flags), text)?;
+                    addr += len;
+                    continue;
+                }
+            }
+            if flags != 0 {
+                writeln!(writer, "{:04X}  {}  {:02X}         DB {:02X}", addr, flag_chars(flags), ram[addr], ram[addr])?;
+                addr += 1;
+                continue;
+            }
+
+            let run_start = addr;
+            while addr < end && self.flags(addr) == 0 {
+                addr += 1;
+            }
+            writeln!(writer, "{:04X}  {}             {} bytes never touched",
+                     run_start, flag_chars(0), addr - run_start)?;
+        }
+        Ok(())
+    }
+}
+
+fn flag_chars(flags: u8) -> String {
+    [(EXECUTED, 'X'), (READ, 'R'), (WRITTEN, 'W')].iter()
+        .map(|&(flag, c)| if flags & flag != 0 { c } else { '-' })
+        .collect()
+}
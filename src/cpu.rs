@@ -1,11 +1,39 @@
 use super::interconnect;
+use instruction::{self, Instruction};
+use fault::{CpuFault, FaultKind};
+use quirks::Quirks;
+use variant::Variant;
+use savestate::SaveStateError;
+use profiler::Profiler;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use time::PreciseTime;
-use std::time::Duration;
-use std::thread;
+use std::io::{Read, Write};
 
 const NUM_GPR: usize = 16;
 const STACK_SIZE: usize = 16;
+const NUM_RPL: usize = 16;
+
+// Stands for no key in save states
+const NO_KEY: u8 = 0xFF;
+
+// What happened when the CPU was asked to execute an instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOutcome {
+    // The instruction ran and did not touch the framebuffer
+    Executed(Instruction),
+
+    // The instruction ran and changed the framebuffer
+    Drew(Instruction),
+
+    // Fx0A is still waiting for a key and will run again, the PC is left on it
+    WaitingForKey,
+
+    // The program has ended, nothing was executed
+    Halted,
+
+    // The instruction could not be carried out, nothing was changed
+    Faulted(CpuFault),
+}
 
 pub struct CPU {
     interconnect: interconnect::Interconnect,
@@ -23,6 +51,32 @@ pub struct CPU {
     reg_sp: u8,
 
     stack: [u16; STACK_SIZE],
+
+    // SUPER-CHIP RPL user flags, saved and restored by Fx75/Fx85
+    reg_rpl: [u8; NUM_RPL],
+
+    halted: bool,
+
+    quirks: Quirks,
+
+    variant: Variant,
+
+    profiler: Option<Profiler>,
+
+    // An Fx0A wait in progress
+    key_wait: Option<KeyWait>,
+}
+
+// What Fx0A has seen of the keys since it started waiting
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyWait {
+    // Keys already held when the wait started, which don't count until
+    // they've been let go
+    ignored: [bool; 16],
+
+    // The key pressed, when the key_wait_release quirk is waiting for it to
+    // be released
+    pressed: Option<u8>,
 }
 
 impl CPU {
@@ -36,373 +90,727 @@ impl CPU {
             reg_dt: 0,
             reg_st: 0,
 
-            reg_pc: 0,
+            reg_pc: interconnect::PROGRAM_START as u16,
 
             reg_sp: 0,
 
             stack: [0; STACK_SIZE],
+
+            reg_rpl: [0; NUM_RPL],
+
+            halted: false,
+
+            quirks: Quirks::default(),
+
+            variant: Variant::default(),
+
+            profiler: None,
+
+            key_wait: None,
         }
     }
 
-    pub fn start(&mut self) {
-        self.reg_pc = 512;
+    // Return the registers and stack to their power on state
+    pub fn reset(&mut self) {
+        self.reg_gpr = [0; NUM_GPR];
+        self.reg_i = 0;
+        self.reg_dt = 0;
+        self.reg_st = 0;
+        self.reg_pc = interconnect::PROGRAM_START as u16;
+        self.reg_sp = 0;
+        self.stack = [0; STACK_SIZE];
+        self.reg_rpl = [0; NUM_RPL];
+        self.halted = false;
+        self.key_wait = None;
+    }
 
-        loop {
-            if self.interconnect.halt {
-                break;
-            }
+    pub fn interconnect(&self) -> &interconnect::Interconnect {
+        &self.interconnect
+    }
 
-            let instr = self.interconnect.read_word(self.reg_pc);
-            let debug = false;
-            if (debug) {
-                println!("Instr: {0:x}", instr);
-                if (self.interconnect.wait_for_step()) {
-                    println!("Regs: {:?}", self.reg_gpr);
-                    println!("PC: {0:x}", self.reg_pc);
-                    println!("SP: {}", self.reg_sp);
-                    println!("PC: {0:x}", self.reg_i);
-                    
-                    println!("DT: {}", self.reg_dt);
-                    println!("ST: {}", self.reg_st);
-                }
-            }
-            self.reg_pc = self.reg_pc + 2;
-            if self.parse_instruction(instr) {
-                break;
-            }
+    pub fn interconnect_mut(&mut self) -> &mut interconnect::Interconnect {
+        &mut self.interconnect
+    }
 
-            self.interconnect.handle_events();
+    pub fn reg_gpr(&self) -> &[u8] {
+        &self.reg_gpr
+    }
 
-            let dt_enabled = self.reg_dt > 0;
-            let st_enabled = self.reg_st > 0;
+    pub fn reg_i(&self) -> u16 {
+        self.reg_i
+    }
 
-            if (!st_enabled) {
-                self.interconnect.stop_beep();
-            }
-            if dt_enabled || st_enabled {
-                thread::sleep(Duration::from_millis(16));
-                if dt_enabled {
-                    self.reg_dt -= 1;
+    pub fn reg_pc(&self) -> u16 {
+        self.reg_pc
+    }
+
+    pub fn reg_sp(&self) -> u8 {
+        self.reg_sp
+    }
+
+    pub fn reg_dt(&self) -> u8 {
+        self.reg_dt
+    }
+
+    pub fn reg_st(&self) -> u8 {
+        self.reg_st
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    // Setters for debuggers. The stack pointer is kept within the stack.
+    pub fn set_reg_gpr(&mut self, index: usize, value: u8) {
+        self.reg_gpr[index] = value;
+    }
+
+    pub fn set_reg_i(&mut self, value: u16) {
+        self.reg_i = value;
+    }
+
+    pub fn set_reg_pc(&mut self, value: u16) {
+        self.reg_pc = value;
+        self.key_wait = None;
+    }
+
+    pub fn set_reg_sp(&mut self, value: u8) {
+        self.reg_sp = if value as usize > STACK_SIZE { STACK_SIZE as u8 } else { value };
+    }
+
+    pub fn set_reg_dt(&mut self, value: u8) {
+        self.reg_dt = value;
+    }
+
+    pub fn set_reg_st(&mut self, value: u8) {
+        self.reg_st = value;
+    }
+
+    pub fn set_stack_entry(&mut self, index: usize, value: u16) {
+        self.stack[index] = value;
+    }
+
+    pub fn reg_rpl(&self) -> &[u8] {
+        &self.reg_rpl
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    // Select which interpreter's instruction set is understood, instructions
+    // from later variants fault as invalid opcodes
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.interconnect.set_memory_size(variant.memory_size());
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Count every instruction executed from now on, or stop with None
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // True while the instruction at the PC is an Fx0A that has already
+    // started waiting for a key
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    // Fetch and execute exactly one instruction
+    pub fn step(&mut self) -> StepOutcome {
+        if self.halted {
+            return StepOutcome::Halted;
+        }
+
+        let pc = self.reg_pc;
+        let result = self.fetch(pc).and_then(|instruction| {
+            self.reg_pc = pc.wrapping_add(instruction.size());
+            self.execute(instruction).map(|outcome| (instruction, outcome))
+        });
+
+        match result {
+            Ok((instruction, outcome)) => {
+                if let Some(ref mut profiler) = self.profiler {
+                    profiler.record(pc, instruction, self.reg_sp);
+                }
+                if outcome == StepOutcome::Halted {
+                    self.halted = true;
                 }
-                if st_enabled {
-                    self.reg_st -= 1;
-                    self.interconnect.start_beep();
+                outcome
+            },
+            Err(kind) => {
+                self.reg_pc = pc;
+                StepOutcome::Faulted(CpuFault {
+                    pc: pc,
+                    opcode: self.interconnect.read_word(pc).unwrap_or(0),
+                    kind: kind,
+                })
+            },
+        }
+    }
+
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> Result<(), SaveStateError> {
+        writer.write_all(&self.reg_gpr)?;
+        writer.write_u16::<BigEndian>(self.reg_i)?;
+        writer.write_u8(self.reg_dt)?;
+        writer.write_u8(self.reg_st)?;
+        writer.write_u16::<BigEndian>(self.reg_pc)?;
+        writer.write_u8(self.reg_sp)?;
+        for addr in self.stack.iter() {
+            writer.write_u16::<BigEndian>(*addr)?;
+        }
+        writer.write_all(&self.reg_rpl)?;
+        writer.write_u8(self.halted as u8)?;
+        match self.key_wait {
+            Some(wait) => {
+                writer.write_u8(1)?;
+                for ignored in wait.ignored.iter() {
+                    writer.write_u8(*ignored as u8)?;
                 }
-            } else {
-                thread::sleep(Duration::from_millis(2));
+                writer.write_u8(wait.pressed.unwrap_or(NO_KEY))?;
+            },
+            None => writer.write_u8(0)?,
+        }
+        self.quirks.write_state(writer)?;
+        writer.write_u8(self.variant as u8)?;
+
+        self.interconnect.write_state(writer)
+    }
+
+    pub fn read_state<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveStateError> {
+        reader.read_exact(&mut self.reg_gpr)?;
+        self.reg_i = reader.read_u16::<BigEndian>()?;
+        self.reg_dt = reader.read_u8()?;
+        self.reg_st = reader.read_u8()?;
+        self.reg_pc = reader.read_u16::<BigEndian>()?;
+        self.reg_sp = reader.read_u8()?;
+        if self.reg_sp as usize > STACK_SIZE {
+            return Err(SaveStateError::Invalid("stack pointer"));
+        }
+        for addr in self.stack.iter_mut() {
+            *addr = reader.read_u16::<BigEndian>()?;
+        }
+        reader.read_exact(&mut self.reg_rpl)?;
+        self.halted = reader.read_u8()? != 0;
+        self.key_wait = if reader.read_u8()? != 0 {
+            let mut ignored = [false; 16];
+            for ignored in ignored.iter_mut() {
+                *ignored = reader.read_u8()? != 0;
             }
+            let pressed = match reader.read_u8()? {
+                NO_KEY => None,
+                key if key < 16 => Some(key),
+                _ => return Err(SaveStateError::Invalid("key wait")),
+            };
+            Some(KeyWait { ignored: ignored, pressed: pressed })
+        } else {
+            None
+        };
+        self.quirks = Quirks::read_state(reader)?;
+        self.variant = match Variant::from_index(reader.read_u8()?) {
+            Some(variant) => variant,
+            None => return Err(SaveStateError::Invalid("variant")),
+        };
+
+        self.interconnect.read_state(reader)
+    }
 
+    // Read and decode the instruction at addr, faulting on opcodes the
+    // selected variant doesn't have
+    fn fetch(&self, addr: u16) -> Result<Instruction, FaultKind> {
+        let instr = self.interconnect.read_word(addr)?;
+        let next = if instr == 0xF000 && self.variant >= Variant::XoChip {
+            self.interconnect.read_word(addr.wrapping_add(2))?
+        } else {
+            0
+        };
+        match instruction::decode(instr, next) {
+            Ok(instruction) if instruction.variant() <= self.variant => Ok(instruction),
+            // 00Cn and 00FB-00FF only became instructions with SCHIP, 00Dn
+            // with XO-CHIP, before that they're machine code calls like any
+            // other 0nnn
+            Ok(_) if instr >> 12 == 0 => Ok(Instruction::Sys(instr & 0x0FFF)),
+            _ => Err(FaultKind::InvalidOpcode),
         }
     }
 
-    fn parse_instruction(&mut self, instr: u16) -> bool {
-        if instr == 0x0000 {
-            return true;
+    // Skip over the next instruction, which in XO-CHIP may be F000 nnnn
+    fn skip_next(&mut self) {
+        let long = self.variant >= Variant::XoChip &&
+            self.interconnect.read_word(self.reg_pc) == Ok(0xF000);
+        self.reg_pc = self.reg_pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    // Count the delay and sound timers down by one 60 Hz tick, which also
+    // advances the VIP random number counter
+    pub fn tick_timers(&mut self) {
+        if self.reg_dt > 0 {
+            self.reg_dt -= 1;
         }
-        let opcode = (instr >> 12) as u8;
+        if self.reg_st > 0 {
+            self.reg_st -= 1;
+        }
+        self.interconnect.tick_rng();
+    }
 
-        match opcode {
-            0x0 => {
-                let filter = ((instr << 4) >> 4) as u16;
+    // Follow an Fx0A wait through the current keys, returning the key once
+    // the wait is over. Any key not held when the wait started will do, and
+    // with the key_wait_release quirk it has to be let go again too, as on
+    // the VIP.
+    fn update_key_wait(&mut self) -> Option<u8> {
+        let keys = self.interconnect.key_state();
+        let release = self.quirks.key_wait_release;
+        let finished = {
+            let wait = self.key_wait.get_or_insert(KeyWait { ignored: keys, pressed: None });
+            for (ignored, &held) in wait.ignored.iter_mut().zip(keys.iter()) {
+                *ignored = *ignored && held;
+            }
+            match wait.pressed {
+                Some(key) => if keys[key as usize] { None } else { Some(key) },
+                None => match (0..16).find(|&key| keys[key as usize] && !wait.ignored[key as usize]) {
+                    Some(key) if release => {
+                        wait.pressed = Some(key);
+                        None
+                    },
+                    found => found,
+                },
+            }
+        };
+        if finished.is_some() {
+            self.key_wait = None;
+        }
+        finished
+    }
+
+    // Execute an already decoded instruction. The program counter is expected
+    // to already point at the following instruction. Nothing is changed if the
+    // instruction faults.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, FaultKind> {
+        use instruction::Instruction::*;
 
+        let mut outcome = StepOutcome::Executed(instruction);
+
+        match instruction {
+            Halt => {
+                return Ok(StepOutcome::Halted);
+            },
+            Sys(addr) => {
                 // 0nnn - SYS addr
                 // Jump to a machine code routine at nnn
-                if filter != 0x0EE && filter != 0x0E0 {
-                    self.reg_pc = filter;
-                }
-
+                self.reg_pc = addr;
+            },
+            Cls => {
                 // 00E0 - CLS
                 // Clear the display
-                if filter == 0x0E0 {
-                    self.interconnect.clear_display();
-                }
-
+                self.interconnect.clear_display();
+                outcome = StepOutcome::Drew(instruction);
+            },
+            Ret => {
                 // 00EE - RET
                 // Return from a subroutine
-                if filter == 0x0EE {
-                    self.reg_pc = self.stack[self.reg_sp as usize];
-                    self.reg_sp = self.reg_sp - 1;
+                if self.reg_sp == 0 {
+                    return Err(FaultKind::StackUnderflow);
                 }
+                self.reg_sp = self.reg_sp - 1;
+                self.reg_pc = self.stack[self.reg_sp as usize];
             },
-            0x1 => {
+            Jp(addr) => {
                 // 1nnn - JP addr
                 // Jump to location nnn
-                let addr = ((instr << 4) >> 4) as u16;
                 self.reg_pc = addr;
             },
-            0x2 => {
+            Call(addr) => {
                 // 2nnn - CALL addr
                 // Call subroutine at nnn
-                let addr = ((instr << 4) >> 4) as u16;
-                self.reg_sp = self.reg_sp + 1;
+                if self.reg_sp as usize == STACK_SIZE {
+                    return Err(FaultKind::StackOverflow);
+                }
                 self.stack[self.reg_sp as usize] = self.reg_pc;
+                self.reg_sp = self.reg_sp + 1;
                 self.reg_pc = addr;
             },
-            0x3 => {
+            SeByte(reg, val) => {
                 // 3xkk - SE Vx, byte
                 // Skip next instruction if Vx = kk
-                let reg = ((instr << 4) >> 12) as usize;
-                let val = ((instr << 8) >> 8) as u8;
                 if self.reg_gpr[reg] == val {
-                    self.reg_pc = self.reg_pc + 2;
+                    self.skip_next();
                 }
             },
-            0x4 => {
+            SneByte(reg, val) => {
                 // 4xkk - SNE Vx, byte
                 // Skip next instruction if Vx != kk
-                let reg = ((instr << 4) >> 12) as usize;
-                let val = ((instr << 8) >> 8) as u8;
                 if self.reg_gpr[reg] != val {
-                    self.reg_pc = self.reg_pc + 2;
+                    self.skip_next();
                 }
             },
-            0x5 => {
+            SeReg(reg_x, reg_y) => {
                 // 5xy0 - SE Vx, Vy
                 // Skip next instrution if Vx = Vy
-                let reg_x = ((instr << 4) >> 12) as usize;
-                let reg_y = ((instr << 8) >> 12) as usize;
-                if self.reg_gpr[reg_x] != self.reg_gpr[reg_y] {
-                    self.reg_pc = self.reg_pc + 2;
+                if self.reg_gpr[reg_x] == self.reg_gpr[reg_y] {
+                    self.skip_next();
                 }
             },
-            0x6 => {
+            LdByte(reg, val) => {
                 // 6xkk - LD Vx, byte
                 // Set Vx = kk
-                let reg = ((instr << 4) >> 12) as usize;
-                let val = ((instr << 8) >> 8) as u8;
                 self.reg_gpr[reg] = val;
             },
-            0x7 => {
+            AddByte(reg, val) => {
                 // 7xkk - ADD Vx, byte
                 // Set Vx = Vx + kk
-                let reg = ((instr << 4) >> 12) as usize;
-                let val = ((instr << 8) >> 8) as u8;
                 self.reg_gpr[reg] = self.reg_gpr[reg].wrapping_add(val);
             },
-            0x8 => {
-                let reg_x = ((instr << 4) >> 12) as usize;
-                let reg_y = ((instr << 8) >> 12) as usize;
-                let last_val = ((instr << 12) >> 12) as u8;
-
+            LdReg(reg_x, reg_y) => {
                 // 8xy0 - LD Vx, Vy
                 // Set Vx = Vy
-                if last_val == 0x0 {
-                    self.reg_gpr[reg_x] = self.reg_gpr[reg_y];
-                }
-
+                self.reg_gpr[reg_x] = self.reg_gpr[reg_y];
+            },
+            Or(reg_x, reg_y) => {
                 // 8xy1 - OR Vx, Vy
                 // Set Vx = Vx OR Vy
-                if last_val == 0x1 {
-                    let new_val = self.reg_gpr[reg_x] | self.reg_gpr[reg_y];
-                    self.reg_gpr[reg_x] = new_val;
-                }
-
+                let new_val = self.reg_gpr[reg_x] | self.reg_gpr[reg_y];
+                self.reg_gpr[reg_x] = new_val;
+                self.reset_vf_after_logic();
+            },
+            And(reg_x, reg_y) => {
                 // 8xy2 - AND Vx, Vy
                 // Set Vx = Vx AND Vy
-                if last_val == 0x2 {
-                    let new_val = self.reg_gpr[reg_x] & self.reg_gpr[reg_y];
-                    self.reg_gpr[reg_x] = new_val;
-                }
-
+                let new_val = self.reg_gpr[reg_x] & self.reg_gpr[reg_y];
+                self.reg_gpr[reg_x] = new_val;
+                self.reset_vf_after_logic();
+            },
+            Xor(reg_x, reg_y) => {
                 // 8xy3 - XOR Vx, Vy
                 // Set Vx = Vx XOR Vy
-                if last_val == 0x3 {
-                    let new_val = self.reg_gpr[reg_x] ^ self.reg_gpr[reg_y];
-                    self.reg_gpr[reg_x] = new_val;
-                }
-
+                let new_val = self.reg_gpr[reg_x] ^ self.reg_gpr[reg_y];
+                self.reg_gpr[reg_x] = new_val;
+                self.reset_vf_after_logic();
+            },
+            AddReg(reg_x, reg_y) => {
                 // 8xy4 - ADD Vx, Vy
                 // Set Vx = Vx + Vy, set VF = carry
-                if last_val == 0x4 {
-                    let original_val = self.reg_gpr[reg_x];
-                    let new_val = original_val.wrapping_add(self.reg_gpr[reg_y]);
-                    let overflowed = new_val < original_val;
-                    self.reg_gpr[reg_x] = new_val;
-                    self.reg_gpr[0xF] = if overflowed { 0x1 } else { 0x0 };
-                }
-
+                let original_val = self.reg_gpr[reg_x];
+                let new_val = original_val.wrapping_add(self.reg_gpr[reg_y]);
+                let overflowed = new_val < original_val;
+                self.reg_gpr[reg_x] = new_val;
+                self.reg_gpr[0xF] = if overflowed { 0x1 } else { 0x0 };
+            },
+            Sub(reg_x, reg_y) => {
                 // 8xy5 - SUB Vx, Vy
                 // Set Vx = Vx - Vy, set VF = NOT borrow
-                if last_val == 0x5 {
-                    let x_val = self.reg_gpr[reg_x];
-                    let y_val = self.reg_gpr[reg_y];
-                    let not_borrowed = x_val > y_val;
-                    let new_val = x_val.wrapping_sub(y_val);
-                    self.reg_gpr[reg_x] = new_val;
-                    self.reg_gpr[0xF] = if not_borrowed { 0x1 } else { 0x0 };
-                }
-
+                let x_val = self.reg_gpr[reg_x];
+                let y_val = self.reg_gpr[reg_y];
+                let not_borrowed = x_val > y_val;
+                let new_val = x_val.wrapping_sub(y_val);
+                self.reg_gpr[reg_x] = new_val;
+                self.reg_gpr[0xF] = if not_borrowed { 0x1 } else { 0x0 };
+            },
+            Shr(reg_x, reg_y) => {
                 // 8xy6 - SHR Vx, Vy
                 // Set Vx = Vy SHIFT_RIGHT 1, set VF to least sig bit
-                if last_val == 0x6 {
-                    let y_val = self.reg_gpr[reg_y];
-                    let least_sig_bit = (y_val << 7) >> 7;
-                    let new_val = y_val >> 1;
-                    self.reg_gpr[reg_x] = new_val;
-                    self.reg_gpr[0xF] = least_sig_bit;
-                }
-
+                let y_val = self.shift_source(reg_x, reg_y);
+                let least_sig_bit = (y_val << 7) >> 7;
+                let new_val = y_val >> 1;
+                self.reg_gpr[reg_x] = new_val;
+                self.reg_gpr[0xF] = least_sig_bit;
+            },
+            Subn(reg_x, reg_y) => {
                 // 8xy7 - SUBN Vx, Vy
                 // Set Vx = Vy - Vx, set VF = NOT borrow
-                if last_val == 0x7 {
-                    let x_val = self.reg_gpr[reg_x];
-                    let y_val = self.reg_gpr[reg_y];
-                    let not_borrowed = y_val > x_val;
-                    let new_val = y_val.wrapping_sub(x_val);
-                    self.reg_gpr[reg_x] = new_val;
-                    self.reg_gpr[0xF] = if not_borrowed { 0x1 } else { 0x0 };
-                }
-
+                let x_val = self.reg_gpr[reg_x];
+                let y_val = self.reg_gpr[reg_y];
+                let not_borrowed = y_val > x_val;
+                let new_val = y_val.wrapping_sub(x_val);
+                self.reg_gpr[reg_x] = new_val;
+                self.reg_gpr[0xF] = if not_borrowed { 0x1 } else { 0x0 };
+            },
+            Shl(reg_x, reg_y) => {
                 // 8xyE - SHL Vx, Vy
                 // Set Vx = Vy SIFT_LEFT 1, set VF to most sig bit
-                if last_val == 0xE {
-                    let y_val = self.reg_gpr[reg_y];
-                    let most_sig_bit = y_val >> 7;
-                    let new_val = y_val << 1;
-                    self.reg_gpr[reg_x] = new_val;
-                    self.reg_gpr[0xF] = most_sig_bit;
-                }
+                let y_val = self.shift_source(reg_x, reg_y);
+                let most_sig_bit = y_val >> 7;
+                let new_val = y_val << 1;
+                self.reg_gpr[reg_x] = new_val;
+                self.reg_gpr[0xF] = most_sig_bit;
             },
-            0x9 => {
+            SneReg(reg_x, reg_y) => {
                 // 9xy0 - SNE Vx, Vy
                 // Skip next instruction if Vx != Vy
-                let reg_x = ((instr << 4) >> 12) as usize;
-                let reg_y = ((instr << 8) >> 12) as usize;
                 if self.reg_gpr[reg_x] != self.reg_gpr[reg_y] {
-                    self.reg_pc = self.reg_pc + 2;
+                    self.skip_next();
                 }
             },
-            0xA => {
-                // Annn - LD 1, addr
+            LdI(addr) => {
+                // Annn - LD I, addr
                 // Set I = nnn
-                let addr = ((instr << 4) >> 4) as u16;
                 self.reg_i = addr;
             },
-            0xB => {
+            JpV0(addr) => {
                 // Bnnn - JP V0, addr
                 // Jump to location nnn + V0
-                let addr = ((instr << 4) >> 4) as u16;
-                let reg_val = self.reg_gpr[0x0] as u16;
+                let reg = if self.quirks.jump_uses_vx { (addr >> 8) as usize } else { 0x0 };
+                let reg_val = self.reg_gpr[reg] as u16;
                 let jmp_addr = reg_val + addr;
                 self.reg_pc = jmp_addr;
             },
-            0xC => {
+            Rnd(reg, val) => {
                 // Cxkk - RND Vx, byte
                 // Set Vx = random byte AND kk
-                let reg = ((instr << 4) >> 12) as usize;
-                let val = ((instr << 8) >> 8) as u8;
                 let rand_val = self.interconnect.get_random_value();
                 let anded_val = rand_val & val;
                 self.reg_gpr[reg] = anded_val;
             },
-            0xD => {
+            Drw(reg_x, reg_y, n) => {
                 // Dxyn - DRW Vx, Vy, nibble
                 // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-                let reg_x = ((instr << 4) >> 12) as usize;
-                let reg_y = ((instr << 8) >> 12) as usize;
                 let x_val = self.reg_gpr[reg_x];
                 let y_val = self.reg_gpr[reg_y];
-                let n = ((instr << 12) >> 12) as u8;
-                let overrode = self.interconnect.display_bytes(n, self.reg_i as usize, x_val as usize, y_val as usize);
+                let clip = self.quirks.clip_sprites;
+                let i = self.reg_i as usize;
+                let overrode = if n == 0 && self.variant >= Variant::SuperChip {
+                    // Dxy0 - DRW Vx, Vy, 0
+                    // SUPER-CHIP draws a 16x16 sprite instead
+                    self.interconnect.display_large_sprite(i, x_val as usize, y_val as usize, clip)?
+                } else {
+                    self.interconnect.display_bytes(n, i, x_val as usize, y_val as usize, clip)?
+                };
                 self.reg_gpr[0xF] = if overrode { 1 } else { 0 };
+                outcome = StepOutcome::Drew(instruction);
             },
-            0xE => {
-                let filter = ((instr << 8) >> 8) as u16;
-                let reg = ((instr << 4) >> 12) as usize;
-                let reg_val = self.reg_gpr[reg];
-
+            Skp(reg) => {
                 // Ex9E - SKP Vx
                 // Skip next instruction if key with the value of Vx is pressed
-                if filter == 0x9E {
-                    if self.interconnect.is_key_pressed(reg_val) {
-                        self.reg_pc = self.reg_pc + 2;
-                    }
+                if self.interconnect.is_key_pressed(self.reg_gpr[reg]) {
+                    self.skip_next();
                 }
-
+            },
+            Sknp(reg) => {
                 // ExA1 - SKNP Vx
                 // Skip next instruction if key with the value of Vx is not pressed
-                if filter == 0xA1 {
-                    if !self.interconnect.is_key_pressed(reg_val) {
-                        self.reg_pc = self.reg_pc + 2;
-                    }
+                if !self.interconnect.is_key_pressed(self.reg_gpr[reg]) {
+                    self.skip_next();
                 }
             },
-            0xF => {
-                let filter = ((instr << 8) >> 8) as u16;
-                let reg = ((instr << 4) >> 12) as usize;
+            LdVxDt(reg) => {
                 // Fx07 - LD Vx, DT
                 // Set Vx = delay timer value
-                if filter == 0x07 {
-                    self.reg_gpr[reg] = self.reg_dt;
-                }
-                
+                self.reg_gpr[reg] = self.reg_dt;
+            },
+            LdVxK(reg) => {
                 // Fx0A  - LD Vx, K
-                // Wait for a key press, store the value of the key in Vx
-                if filter == 0x0A {
-                    let key = self.reg_gpr[reg];
-                    self.interconnect.wait_for_keypress(key);
+                // Wait for a key press, store the value of the key in Vx.
+                // Rather than block, the instruction runs again until a key
+                // goes down, so timers and the display carry on meanwhile.
+                match self.update_key_wait() {
+                    Some(key) => self.reg_gpr[reg] = key,
+                    None => {
+                        self.reg_pc = self.reg_pc.wrapping_sub(2);
+                        outcome = StepOutcome::WaitingForKey;
+                    },
                 }
-
+            },
+            LdDtVx(reg) => {
                 // Fx15 - LD DT, Vx
                 // Set delay timer = Vx
-                if filter == 0x15 {
-                    self.reg_dt = self.reg_gpr[reg];
-                }
-
+                self.reg_dt = self.reg_gpr[reg];
+            },
+            LdStVx(reg) => {
                 // Fx18 - LD ST, Vx
                 // Set sound timer = Vx
-                if filter == 0x18 {
-                    self.reg_st = self.reg_gpr[reg];
-                }
-
+                self.reg_st = self.reg_gpr[reg];
+            },
+            AddIVx(reg) => {
                 // Fx1E - ADD I, Vx
                 // Set I = I + Vx
-                if filter == 0x1E {
-                    self.reg_i = self.reg_i + (self.reg_gpr[reg] as u16);
-                }
-                
+                self.reg_i = self.reg_i.wrapping_add(self.reg_gpr[reg] as u16);
+            },
+            LdFVx(reg) => {
                 // Fx29 - LD F, Vx
                 // Set I = location of sprite for digit Vx
-                if filter == 0x29 {
-                    let digit = self.reg_gpr[reg];
-                    self.reg_i = 0x5 * digit as u16;
-                }
-                
+                let digit = self.reg_gpr[reg];
+                self.reg_i = 0x5 * digit as u16;
+            },
+            LdBVx(reg) => {
                 // Fx33 - LD B, Vx
                 // Store BCD representation of Vx in Memory Locations I, I+1, and I+2
-                if filter == 0x33 {
-                    let val = self.reg_gpr[reg];
-                    let dig1 = val / 100;
-                    let dig2 = (val % 100) / 10;
-                    let dig3 = val % 10;
-                    let i = self.reg_i as usize;
-                    self.interconnect.write_to_addr(i, dig1);
-                    self.interconnect.write_to_addr(i + 1, dig2);
-                    self.interconnect.write_to_addr(i + 2, dig3);
-                }
-                
+                let val = self.reg_gpr[reg];
+                let dig1 = val / 100;
+                let dig2 = (val % 100) / 10;
+                let dig3 = val % 10;
+                let i = self.reg_i as usize;
+                self.interconnect.check_range(i, 3)?;
+                self.interconnect.write_to_addr(i, dig1)?;
+                self.interconnect.write_to_addr(i + 1, dig2)?;
+                self.interconnect.write_to_addr(i + 2, dig3)?;
+            },
+            LdIVx(reg) => {
                 // Fx55 - LD [I], Vx
                 // Store registers V0 through Vx in memory starting at location I
-                if filter == 0x55 {
-                    let mem_index = self.reg_i as usize;
-                    for n in 0..reg+1 {
-                        self.interconnect.write_to_addr(mem_index + n, self.reg_gpr[n]);
-                    }
+                let mem_index = self.reg_i as usize;
+                self.interconnect.check_range(mem_index, reg + 1)?;
+                for n in 0..reg+1 {
+                    self.interconnect.write_to_addr(mem_index + n, self.reg_gpr[n])?;
+                }
+                if self.quirks.load_store_increments_i {
+                    self.reg_i = self.reg_i.wrapping_add(reg as u16 + 1);
                 }
-                
+            },
+            LdVxI(reg) => {
                 // Fx65 - LD Vx, [I]
                 // Read registers V0 through Vx from memory starting at locaiton I
-                if filter == 0x65 {
-                    let mem_index = self.reg_i as usize;
-                    for n in 0..reg+1 {
-                        self.reg_gpr[n] = self.interconnect.get_from_addr(mem_index + n);
-                    }
+                let mem_index = self.reg_i as usize;
+                self.interconnect.check_range(mem_index, reg + 1)?;
+                for n in 0..reg+1 {
+                    self.reg_gpr[n] = self.interconnect.get_from_addr(mem_index + n)?;
                 }
+                if self.quirks.load_store_increments_i {
+                    self.reg_i = self.reg_i.wrapping_add(reg as u16 + 1);
+                }
+            },
+            ScrollDown(n) => {
+                // 00Cn - SCD nibble
+                // Scroll the display down by n pixels
+                self.interconnect.scroll_display_down(n as usize);
+                outcome = StepOutcome::Drew(instruction);
+            },
+            ScrollRight => {
+                // 00FB - SCR
+                // Scroll the display right by 4 pixels
+                self.interconnect.scroll_display_right(4);
+                outcome = StepOutcome::Drew(instruction);
+            },
+            ScrollLeft => {
+                // 00FC - SCL
+                // Scroll the display left by 4 pixels
+                self.interconnect.scroll_display_left(4);
+                outcome = StepOutcome::Drew(instruction);
+            },
+            Exit => {
+                // 00FD - EXIT
+                // Exit the interpreter
+                return Ok(StepOutcome::Halted);
+            },
+            Lores => {
+                // 00FE - LOW
+                // Switch to 64x32 low resolution mode
+                self.interconnect.set_hires(false);
+                outcome = StepOutcome::Drew(instruction);
             },
-            _ => {
-                println!("Unknown Opcode");
+            Hires => {
+                // 00FF - HIGH
+                // Switch to 128x64 high resolution mode
+                self.interconnect.set_hires(true);
+                outcome = StepOutcome::Drew(instruction);
             },
+            LdHfVx(reg) => {
+                // Fx30 - LD HF, Vx
+                // Set I = location of the large sprite for digit Vx
+                let digit = (self.reg_gpr[reg] & 0xF) as u16;
+                self.reg_i = interconnect::LARGE_FONT_START as u16 + 10 * digit;
+            },
+            LdRVx(reg) => {
+                // Fx75 - LD R, Vx
+                // Store registers V0 through Vx in the RPL user flags
+                for n in 0..reg+1 {
+                    self.reg_rpl[n] = self.reg_gpr[n];
+                }
+            },
+            LdVxR(reg) => {
+                // Fx85 - LD Vx, R
+                // Read registers V0 through Vx from the RPL user flags
+                for n in 0..reg+1 {
+                    self.reg_gpr[n] = self.reg_rpl[n];
+                }
+            },
+            ScrollUp(n) => {
+                // 00Dn - SCU nibble
+                // Scroll the display up by n pixels
+                self.interconnect.scroll_display_up(n as usize);
+                outcome = StepOutcome::Drew(instruction);
+            },
+            SaveRange(reg_x, reg_y) => {
+                // 5xy2 - LD [I], Vx-Vy
+                // Store registers Vx through Vy in memory starting at location I, I is unchanged
+                let regs = register_range(reg_x, reg_y);
+                self.interconnect.check_range(self.reg_i as usize, regs.len())?;
+                for (n, reg) in regs.into_iter().enumerate() {
+                    self.interconnect.write_to_addr(self.reg_i as usize + n, self.reg_gpr[reg])?;
+                }
+            },
+            LoadRange(reg_x, reg_y) => {
+                // 5xy3 - LD Vx-Vy, [I]
+                // Read registers Vx through Vy from memory starting at location I, I is unchanged
+                let regs = register_range(reg_x, reg_y);
+                self.interconnect.check_range(self.reg_i as usize, regs.len())?;
+                for (n, reg) in regs.into_iter().enumerate() {
+                    self.reg_gpr[reg] = self.interconnect.get_from_addr(self.reg_i as usize + n)?;
+                }
+            },
+            LdILong(addr) => {
+                // F000 nnnn - LD I, long addr
+                // Set I = the 16 bit address in the following word
+                self.reg_i = addr;
+            },
+            Plane(planes) => {
+                // Fn01 - PLANE n
+                // Select the bitplanes later drawing, clearing and scrolling affect
+                self.interconnect.select_planes(planes);
+            },
+            Audio => {
+                // F002 - AUDIO
+                // Load the 16 byte audio pattern from memory starting at location I
+                self.interconnect.load_audio_pattern(self.reg_i as usize)?;
+            },
+            Pitch(reg) => {
+                // Fx3A - PITCH Vx
+                // Set the audio pattern playback rate to 4000 * 2^((Vx - 64) / 48) bits per second
+                self.interconnect.set_pitch(self.reg_gpr[reg]);
+            },
+        }
+        Ok(outcome)
+    }
+
+    // Value 8xy6/8xyE shift, depending on which interpreter is emulated
+    fn shift_source(&self, reg_x: usize, reg_y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.reg_gpr[reg_y]
+        } else {
+            self.reg_gpr[reg_x]
         }
-        return false;
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.reg_gpr[0xF] = 0;
+        }
+    }
+}
+
+// Vx through Vy in the order 5xy2/5xy3 transfer them, which may be descending
+fn register_range(reg_x: usize, reg_y: usize) -> Vec<usize> {
+    if reg_x <= reg_y {
+        (reg_x..reg_y + 1).collect()
+    } else {
+        (reg_y..reg_x + 1).rev().collect()
     }
 }
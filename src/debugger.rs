@@ -0,0 +1,398 @@
+use std::cmp;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use cpu::{CPU, StepOutcome};
+use disasm::{self, DisasmLine};
+use fault::CpuFault;
+use instruction::Instruction;
+use interconnect::{AccessKind, MemoryAccess};
+
+const PROMPT: &'static str = "(c8db) ";
+
+const HELP: &'static str = "Commands, addresses are in hex:
+    c, continue             Run until a breakpoint or watchpoint is hit
+    s, step [n]             Execute n instructions (default 1)
+    n, next                 Step, running over subroutine calls
+    finish                  Run until the current subroutine returns
+    b, break <addr>         Set a breakpoint
+    d, delete <addr>        Remove a breakpoint
+    watch <addr>[-<end>] [r|w|rw]
+                            Stop when the range is read and/or written (default w)
+    unwatch <n>             Remove watchpoint n
+    i, info                 List breakpoints and watchpoints
+    r, regs                 Show registers
+    stack                   Show the call stack
+    x <addr> [len]          Dump len bytes of memory (default 64)
+    l, disasm [addr] [n]    Disassemble n instructions (default around PC)
+    q, quit                 Stop the emulator
+An empty line repeats the last command.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// Stops execution when an instruction accesses any address from start to
+// end inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind_matches = matches!((self.kind, access.kind),
+                                    (WatchKind::ReadWrite, _) |
+                                    (WatchKind::Read, AccessKind::Read) |
+                                    (WatchKind::Write, AccessKind::Write));
+        kind_matches && access.addr <= self.end && access.addr + access.len > self.start
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunState {
+    Running,
+    Paused,
+
+    // Pause after this many more instructions
+    Stepping(u32),
+
+    // Pause once PC is back at the instruction after a CALL, with the stack
+    // at the depth it had before the call
+    StepOver { return_addr: u16, sp: u8 },
+
+    // Pause once a RET takes the stack below this depth
+    StepOut { sp: u8 },
+}
+
+// A debugger driven by commands typed at a terminal prompt. Chip8 consults it
+// around every instruction and stops running the CPU while it is paused,
+// carrying on presenting the display and polling input.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    state: RunState,
+
+    // A breakpoint here doesn't stop the first instruction after resuming,
+    // otherwise there would be no way to continue from it
+    resume_pc: Option<u16>,
+
+    lines: Receiver<String>,
+    last_line: String,
+}
+
+impl Debugger {
+    // Take commands from the given channel, one line per command
+    pub fn new(lines: Receiver<String>) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            state: RunState::Paused,
+            resume_pc: None,
+            lines: lines,
+            last_line: String::new(),
+        }
+    }
+
+    // Take commands from standard input, read on a separate thread so the
+    // emulator can keep the window alive while waiting for them
+    pub fn from_stdin() -> Debugger {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+        Debugger::new(receiver)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state == RunState::Paused
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Stop and show where, as when the debugger is first attached
+    pub fn pause(&mut self, cpu: &CPU, reason: &str) {
+        self.state = RunState::Paused;
+        println!("{}", reason);
+        print_location(cpu);
+        prompt();
+    }
+
+    // Called before each instruction, returns true if execution should stop
+    // before it instead
+    pub fn before_step(&mut self, cpu: &CPU) -> bool {
+        let pc = cpu.reg_pc();
+        let resuming = self.resume_pc.take() == Some(pc);
+        // An Fx0A that's waiting stopped at its breakpoint when it started
+        if !resuming && !cpu.is_waiting_for_key() && self.breakpoints.contains(&pc) {
+            self.pause(cpu, &format!("Breakpoint at {:04X}", pc));
+            return true;
+        }
+        false
+    }
+
+    // Called after each instruction with the PC it was fetched from and the
+    // memory it accessed
+    pub fn after_step(&mut self, cpu: &CPU, pc: u16, outcome: StepOutcome, accesses: &[MemoryAccess]) {
+        match outcome {
+            StepOutcome::Faulted(fault) => return self.fault(cpu, fault),
+            StepOutcome::Halted => return self.pause(cpu, "Program exited"),
+            _ => {},
+        }
+
+        for access in accesses {
+            if let Some(n) = self.watchpoints.iter().position(|watchpoint| watchpoint.matches(access)) {
+                let kind = if access.kind == AccessKind::Read { "read" } else { "write" };
+                let reason = format!("Watchpoint {} hit: {} of {} byte(s) at {:04X} by the instruction at {:04X}",
+                                     n, kind, access.len, access.addr, pc);
+                self.pause(cpu, &reason);
+                return;
+            }
+        }
+
+        match self.state {
+            RunState::Stepping(1) => self.pause(cpu, "Stepped"),
+            RunState::Stepping(n) => self.state = RunState::Stepping(n - 1),
+            RunState::StepOver { return_addr, sp } => {
+                if cpu.reg_pc() == return_addr && cpu.reg_sp() == sp {
+                    self.pause(cpu, "Stepped over");
+                }
+            },
+            RunState::StepOut { sp } => {
+                if cpu.reg_sp() < sp {
+                    self.pause(cpu, "Returned");
+                }
+            },
+            RunState::Running | RunState::Paused => {},
+        }
+    }
+
+    // The program can't go on, stop so the state it faulted in can be looked at
+    pub fn fault(&mut self, cpu: &CPU, fault: CpuFault) {
+        self.pause(cpu, &format!("CPU fault: {}", fault));
+    }
+
+    // Carry out the commands typed since the last call, stopping early if one
+    // resumes execution. Returns true if the user asked to quit.
+    pub fn process_commands(&mut self, cpu: &CPU) -> bool {
+        while self.is_paused() {
+            match self.lines.try_recv() {
+                Ok(line) => if self.handle_line(&line, cpu) {
+                    return true;
+                },
+                Err(TryRecvError::Empty) => break,
+                // Standard input was closed, nobody can resume
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+        false
+    }
+
+    // Returns true for quit
+    fn handle_line(&mut self, line: &str, cpu: &CPU) -> bool {
+        let line = if line.trim().is_empty() {
+            self.last_line.clone()
+        } else {
+            self.last_line = line.to_string();
+            line.to_string()
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
+            prompt();
+            return false;
+        }
+
+        match self.command(&args, cpu) {
+            Ok(true) => return true,
+            Ok(false) => {},
+            Err(message) => println!("{}", message),
+        }
+        if self.is_paused() {
+            prompt();
+        }
+        false
+    }
+
+    fn command(&mut self, args: &[&str], cpu: &CPU) -> Result<bool, String> {
+        let ram = cpu.interconnect().ram();
+        match args[0] {
+            "c" | "continue" => self.resume(cpu, RunState::Running),
+            "s" | "step" => {
+                let count = match args.get(1) {
+                    Some(count) => count.parse().map_err(|_| format!("not a count: {}", count))?,
+                    None => 1,
+                };
+                if count > 0 {
+                    self.resume(cpu, RunState::Stepping(count));
+                }
+            },
+            "n" | "next" => {
+                let pc = cpu.reg_pc();
+                let state = match DisasmLine::at(ram, pc as usize) {
+                    Some(DisasmLine { instruction: Some(Instruction::Call(_)), .. }) =>
+                        RunState::StepOver { return_addr: pc.wrapping_add(2), sp: cpu.reg_sp() },
+                    _ => RunState::Stepping(1),
+                };
+                self.resume(cpu, state);
+            },
+            "finish" => {
+                if cpu.reg_sp() == 0 {
+                    return Err("not in a subroutine".to_string());
+                }
+                self.resume(cpu, RunState::StepOut { sp: cpu.reg_sp() });
+            },
+            "b" | "break" => {
+                let addr = parse_addr(args.get(1))?;
+                self.breakpoints.insert(addr as u16);
+                println!("Breakpoint at {:04X}", addr);
+            },
+            "d" | "delete" => {
+                let addr = parse_addr(args.get(1))?;
+                if !self.breakpoints.remove(&(addr as u16)) {
+                    return Err(format!("no breakpoint at {:04X}", addr));
+                }
+            },
+            "watch" => {
+                let range = args.get(1).ok_or("watch needs an address")?;
+                let mut bounds = range.splitn(2, '-');
+                let start = parse_addr(bounds.next().as_ref())?;
+                let end = match bounds.next() {
+                    Some(end) => parse_addr(Some(&end))?,
+                    None => start,
+                };
+                if end < start {
+                    return Err(format!("{:04X} comes after {:04X}", start, end));
+                }
+                let kind = match args.get(2).cloned() {
+                    None | Some("w") => WatchKind::Write,
+                    Some("r") => WatchKind::Read,
+                    Some("rw") => WatchKind::ReadWrite,
+                    Some(other) => return Err(format!("expected r, w or rw, got {}", other)),
+                };
+                self.watchpoints.push(Watchpoint { start: start, end: end, kind: kind });
+                println!("Watchpoint {} on {:04X}-{:04X}", self.watchpoints.len() - 1, start, end);
+            },
+            "unwatch" => {
+                let n: usize = args.get(1).and_then(|n| n.parse().ok()).ok_or("unwatch needs a watchpoint number")?;
+                if n >= self.watchpoints.len() {
+                    return Err(format!("no watchpoint {}", n));
+                }
+                self.watchpoints.remove(n);
+            },
+            "i" | "info" => {
+                for addr in self.breakpoints.iter() {
+                    println!("Breakpoint at {:04X}", addr);
+                }
+                for (n, watchpoint) in self.watchpoints.iter().enumerate() {
+                    println!("Watchpoint {} on {:04X}-{:04X} ({:?})", n, watchpoint.start, watchpoint.end, watchpoint.kind);
+                }
+            },
+            "r" | "regs" => print_registers(cpu),
+            "stack" => {
+                if cpu.reg_sp() == 0 {
+                    println!("Stack is empty");
+                }
+                for depth in (0..cpu.reg_sp() as usize).rev() {
+                    println!("#{} returns to {:04X}", depth, cpu.stack()[depth]);
+                }
+            },
+            "x" => {
+                let addr = parse_addr(args.get(1))?;
+                let len = match args.get(2) {
+                    Some(_) => parse_addr(args.get(2))?,
+                    None => 64,
+                };
+                let end = cmp::min(addr.saturating_add(len), ram.len());
+                if addr >= end {
+                    return Err(format!("{:04X} is outside memory", addr));
+                }
+                for row in (addr..end).step_by(16) {
+                    let row_end = if row + 16 > end { end } else { row + 16 };
+                    let bytes: Vec<String> = ram[row..row_end].iter().map(|b| format!("{:02X}", b)).collect();
+                    println!("{:04X}: {}", row, bytes.join(" "));
+                }
+            },
+            "l" | "disasm" => {
+                let pc = cpu.reg_pc() as usize;
+                let addr = match args.get(1) {
+                    Some(_) => parse_addr(args.get(1))?,
+                    // A few instructions of context before PC
+                    None => if pc >= 6 { pc - 6 } else { pc },
+                };
+                let count = match args.get(2) {
+                    Some(count) => count.parse().map_err(|_| format!("not a count: {}", count))?,
+                    None => 10,
+                };
+                for line in disasm::disassemble(ram, addr, count) {
+                    print_line(&line, cpu, &self.breakpoints);
+                }
+            },
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(true),
+            other => return Err(format!("unknown command {}, try help", other)),
+        }
+        Ok(false)
+    }
+
+    fn resume(&mut self, cpu: &CPU, state: RunState) {
+        self.state = state;
+        self.resume_pc = Some(cpu.reg_pc());
+    }
+}
+
+fn parse_addr(arg: Option<&&str>) -> Result<usize, String> {
+    let arg = match arg {
+        Some(arg) => arg,
+        None => return Err("missing address".to_string()),
+    };
+    let digits = arg.trim_start_matches("0x").trim_start_matches('$');
+    usize::from_str_radix(digits, 16).map_err(|_| format!("not a hex address: {}", arg))
+}
+
+fn prompt() {
+    print!("{}", PROMPT);
+    io::stdout().flush().unwrap();
+}
+
+fn print_registers(cpu: &CPU) {
+    let regs: Vec<String> = cpu.reg_gpr().iter().map(|r| format!("{:02X}", r)).collect();
+    println!("V0-V7: {}", regs[..8].join(" "));
+    println!("V8-VF: {}", regs[8..].join(" "));
+    println!("I: {:04X}  PC: {:04X}  SP: {}  DT: {:02X}  ST: {:02X}",
+             cpu.reg_i(), cpu.reg_pc(), cpu.reg_sp(), cpu.reg_dt(), cpu.reg_st());
+}
+
+// Show the instruction about to run
+fn print_location(cpu: &CPU) {
+    match DisasmLine::at(cpu.interconnect().ram(), cpu.reg_pc() as usize) {
+        Some(line) => print_line(&line, cpu, &BTreeSet::new()),
+        None => println!("PC {:04X} is outside memory", cpu.reg_pc()),
+    }
+}
+
+fn print_line(line: &DisasmLine, cpu: &CPU, breakpoints: &BTreeSet<u16>) {
+    let current = if line.addr == cpu.reg_pc() as usize { "=>" } else { "  " };
+    let breakpoint = if breakpoints.contains(&(line.addr as u16)) { "*" } else { " " };
+    println!("{}{} {}", current, breakpoint, line);
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use instruction::{self, Instruction};
+
+// One instruction's worth of memory, decoded for display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisasmLine {
+    pub addr: usize,
+    pub opcode: u16,
+
+    // The word after the opcode, only meaningful for F000 nnnn
+    pub next: u16,
+
+    // None if the opcode isn't an instruction, most likely because it's data
+    pub instruction: Option<Instruction>,
+}
+
+impl DisasmLine {
+    // Decode the instruction at addr, None if it doesn't fit in memory
+    pub fn at(memory: &[u8], addr: usize) -> Option<DisasmLine> {
+        if addr.saturating_add(2) > memory.len() {
+            return None;
+        }
+        let word = |addr: usize| {
+            if addr + 2 > memory.len() {
+                0
+            } else {
+                (memory[addr] as u16) << 8 | memory[addr + 1] as u16
+            }
+        };
+        let opcode = word(addr);
+        let next = word(addr + 2);
+        Some(DisasmLine {
+            addr: addr,
+            opcode: opcode,
+            next: next,
+            instruction: instruction::decode(opcode, next).ok(),
+        })
+    }
+
+    // Bytes taken up, so the next line starts at addr + size
+    pub fn size(&self) -> usize {
+        self.instruction.map_or(2, |instruction| instruction.size() as usize)
+    }
+}
+
+impl fmt::Display for DisasmLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.instruction {
+            Some(instruction) if self.size() == 4 =>
+                write!(f, "{:04X}: {:04X} {:04X}  {}", self.addr, self.opcode, self.next, instruction),
+            Some(instruction) =>
+                write!(f, "{:04X}: {:04X}       {}", self.addr, self.opcode, instruction),
+            None => write!(f, "{:04X}: {:04X}       DW {:04X}", self.addr, self.opcode, self.opcode),
+        }
+    }
+}
+
+// count instructions starting at addr, fewer if memory runs out
+pub fn disassemble(memory: &[u8], addr: usize, count: usize) -> Vec<DisasmLine> {
+    let mut lines = Vec::new();
+    let mut addr = addr;
+    while lines.len() < count {
+        match DisasmLine::at(memory, addr) {
+            Some(line) => {
+                addr += line.size();
+                lines.push(line);
+            },
+            None => break,
+        }
+    }
+    lines
+}
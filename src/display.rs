@@ -0,0 +1,158 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// XO-CHIP has two bitplanes, each pixel is a mask of the planes lit there
+pub const NUM_PLANES: usize = 2;
+pub const ALL_PLANES: u8 = 0b11;
+
+// The framebuffer. Storage is always sized for SUPER-CHIP high resolution,
+// in low resolution only the top left 64x32 pixels are in use.
+#[derive(Clone, Copy)]
+pub struct Display {
+    hires: bool,
+
+    // Planes affected by drawing, clearing and scrolling, selected by Fn01
+    planes: u8,
+
+    // Row major, HIRES_WIDTH pixels per row regardless of resolution
+    pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            hires: false,
+            planes: 0b01,
+            pixels: [0; HIRES_WIDTH * HIRES_HEIGHT],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // Switch resolution, which also clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [0; HIRES_WIDTH * HIRES_HEIGHT];
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & ALL_PLANES;
+    }
+
+    // True if the pixel is lit in any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.color(x, y) != 0
+    }
+
+    // The planes lit at this pixel, 0 to 3, for picking one of four colours
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * HIRES_WIDTH + x]
+    }
+
+    // Clear the selected planes
+    pub fn clear(&mut self) {
+        let keep = !self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= keep;
+        }
+    }
+
+    // XOR one byte of sprite data onto row y of the given plane starting at
+    // column x. Pixels past the right edge are dropped when clipping,
+    // otherwise they wrap around. Returns true if a lit pixel was turned off.
+    pub fn draw_byte(&mut self, byte: u8, x: usize, y: usize, plane: u8, clip: bool) -> bool {
+        let width = self.width();
+        let mut overrode = false;
+        for i in 0..8 {
+            if clip && x + i >= width {
+                break;
+            }
+            if (byte << i) & 0x80 == 0 {
+                continue;
+            }
+            let index = y * HIRES_WIDTH + (x + i) % width;
+            if self.pixels[index] & plane != 0 {
+                overrode = true;
+            }
+            self.pixels[index] ^= plane;
+        }
+        overrode
+    }
+
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u8(self.hires as u8)?;
+        writer.write_u8(self.planes)?;
+        writer.write_all(&self.pixels)
+    }
+
+    pub fn read_state<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        self.hires = reader.read_u8()? != 0;
+        self.set_planes(reader.read_u8()?);
+        reader.read_exact(&mut self.pixels)?;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= ALL_PLANES;
+        }
+        Ok(())
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    // Move the selected planes by dx, dy. Pixels scrolled off the edge are
+    // lost and the uncovered ones are blank.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.pixels;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if src_x >= 0 && src_x < width && src_y >= 0 && src_y < height {
+                    old[(src_y * HIRES_WIDTH as isize + src_x) as usize] & self.planes
+                } else {
+                    0
+                };
+                let index = (y * HIRES_WIDTH as isize + x) as usize;
+                self.pixels[index] = (old[index] & !self.planes) | moved;
+            }
+        }
+    }
+}
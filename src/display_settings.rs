@@ -0,0 +1,255 @@
+use config::Config;
+
+// Window pixels per CHIP-8 pixel in low resolution
+pub const DEFAULT_SCALE: u32 = 10;
+
+pub const PALETTE_NAMES: [&'static str; 5] = ["mono", "amber", "green", "lcd", "octo"];
+
+// The fraction of its remaining glow a fading pixel has removed each frame
+pub const DEFAULT_DECAY: f32 = 0.35;
+
+pub const PERSISTENCE_NAMES: [&'static str; 3] = ["off", "fade", "blend"];
+
+pub type Rgb = (u8, u8, u8);
+
+// How long pixels stay visible after they go out, which hides the flicker of
+// sprites being erased and drawn again. Only what's shown is affected, the
+// emulated display is unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Persistence {
+    // Every frame shown as it is
+    Off,
+
+    // Pixels light at once and fade out over several frames, like a
+    // phosphor screen
+    Fade,
+
+    // Every frame shown mixed half and half with the one before
+    Blend,
+}
+
+impl Persistence {
+    pub fn from_name(name: &str) -> Option<Persistence> {
+        match name {
+            "off" => Some(Persistence::Off),
+            "fade" => Some(Persistence::Fade),
+            "blend" => Some(Persistence::Blend),
+            _ => None,
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Persistence, String> {
+        Persistence::from_name(value)
+            .ok_or_else(|| format!("unknown persistence {}, expected one of {}", value, PERSISTENCE_NAMES.join(", ")))
+    }
+}
+
+// Colours for no plane, plane 1, plane 2 and both planes lit. Programs that
+// don't use XO-CHIP's second plane only show the first two.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Palette> {
+        let colors = match name {
+            "mono" => [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)],
+            "amber" => [(0, 0, 0), (255, 176, 0), (170, 117, 0), (85, 59, 0)],
+            "green" => [(0, 16, 0), (51, 255, 102), (34, 170, 68), (17, 85, 34)],
+            "lcd" => [(155, 188, 15), (15, 56, 15), (48, 98, 48), (139, 172, 15)],
+            "octo" => [(153, 102, 0), (255, 204, 0), (255, 102, 0), (102, 34, 0)],
+            _ => return None,
+        };
+        Some(Palette { colors: colors })
+    }
+
+    // A palette name, or two to four comma separated hex colours: the
+    // background, the foreground, then XO-CHIP's plane 2 and both planes. The
+    // plane 2 and both planes colours default to blends of the first two.
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::from_name(value) {
+            return Ok(palette);
+        }
+
+        let mut colors = Vec::new();
+        for color in value.split(',') {
+            colors.push(parse_color(color.trim())?);
+        }
+        if colors.len() < 2 || colors.len() > 4 {
+            return Err(format!("unknown palette {}, expected one of {} or 2 to 4 hex colours",
+                               value, PALETTE_NAMES.join(", ")));
+        }
+        let (background, foreground) = (colors[0], colors[1]);
+        if colors.len() < 3 {
+            colors.push(blend(background, foreground, 2, 3));
+        }
+        if colors.len() < 4 {
+            colors.push(blend(background, foreground, 1, 3));
+        }
+        Ok(Palette { colors: [colors[0], colors[1], colors[2], colors[3]] })
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::from_name("mono").unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplaySettings {
+    pub scale: u32,
+    pub palette: Palette,
+    pub fullscreen: bool,
+    pub persistence: Persistence,
+
+    // The fraction of its remaining glow removed from a fading pixel each
+    // frame. 1 removes it all at once, the same as no persistence.
+    pub decay: f32,
+}
+
+impl DisplaySettings {
+    // Read the [display] section
+    //
+    //     [display]
+    //     scale = 8
+    //     palette = amber
+    //     fullscreen = true
+    //     persistence = fade
+    //     decay = 0.5
+    pub fn from_config(config: &Config) -> Result<DisplaySettings, String> {
+        let mut settings = DisplaySettings::default();
+        if let Some(scale) = config.get("display", "scale") {
+            settings.scale = parse_scale(scale)?;
+        }
+        if let Some(palette) = config.get("display", "palette") {
+            settings.palette = Palette::parse(palette)?;
+        }
+        if let Some(fullscreen) = config.get("display", "fullscreen") {
+            settings.fullscreen = match fullscreen {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" => false,
+                _ => return Err(format!("fullscreen expects true or false, got {}", fullscreen)),
+            };
+        }
+        if let Some(persistence) = config.get("display", "persistence") {
+            settings.persistence = Persistence::parse(persistence)?;
+        }
+        if let Some(decay) = config.get("display", "decay") {
+            settings.decay = parse_decay(decay)?;
+        }
+        Ok(settings)
+    }
+}
+
+impl Default for DisplaySettings {
+    fn default() -> DisplaySettings {
+        DisplaySettings {
+            scale: DEFAULT_SCALE,
+            palette: Palette::default(),
+            fullscreen: false,
+            persistence: Persistence::Off,
+            decay: DEFAULT_DECAY,
+        }
+    }
+}
+
+pub fn parse_scale(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(scale) if scale > 0 && scale <= 64 => Ok(scale),
+        _ => Err(format!("scale expects a whole number from 1 to 64, got {}", value)),
+    }
+}
+
+pub fn parse_decay(value: &str) -> Result<f32, String> {
+    match value.parse() {
+        Ok(decay) if decay > 0.0 && decay <= 1.0 => Ok(decay),
+        _ => Err(format!("decay expects a fraction above 0 and up to 1, got {}", value)),
+    }
+}
+
+// RRGGBB, with or without a leading #
+fn parse_color(value: &str) -> Result<Rgb, String> {
+    let hex = value.trim_start_matches('#');
+    let rgb = match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => rgb,
+        _ => return Err(format!("{} is not a colour, expected RRGGBB in hex", value)),
+    };
+    Ok(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+// weight parts in parts of the way from one colour to the other
+fn blend(from: Rgb, to: Rgb, weight: u32, parts: u32) -> Rgb {
+    let mix = |from: u8, to: u8| ((from as u32 * (parts - weight) + to as u32 * weight) / parts) as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use super::{DisplaySettings, Palette, Persistence, DEFAULT_DECAY, DEFAULT_SCALE};
+
+    fn from_config(text: &str) -> Result<DisplaySettings, String> {
+        DisplaySettings::from_config(&Config::parse(text).unwrap())
+    }
+
+    #[test]
+    fn missing_section_is_the_default() {
+        let settings = from_config("[keys]\npreset = hex\n").unwrap();
+        assert_eq!(settings, DisplaySettings::default());
+        assert_eq!(settings.scale, DEFAULT_SCALE);
+        assert_eq!(settings.palette, Palette::from_name("mono").unwrap());
+        assert!(!settings.fullscreen);
+        assert_eq!(settings.persistence, Persistence::Off);
+        assert_eq!(settings.decay, DEFAULT_DECAY);
+    }
+
+    #[test]
+    fn section_values() {
+        let settings = from_config("[display]\nscale = 4\npalette = amber\nfullscreen = yes\n").unwrap();
+        assert_eq!(settings.scale, 4);
+        assert_eq!(settings.palette, Palette::from_name("amber").unwrap());
+        assert!(settings.fullscreen);
+    }
+
+    #[test]
+    fn hex_colours() {
+        let palette = Palette::parse("#000000, FFFFFF").unwrap();
+        assert_eq!(palette.colors, [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)]);
+
+        let palette = Palette::parse("102030,405060,708090,A0B0c0").unwrap();
+        assert_eq!(palette.colors, [(0x10, 0x20, 0x30), (0x40, 0x50, 0x60), (0x70, 0x80, 0x90), (0xA0, 0xB0, 0xC0)]);
+    }
+
+    #[test]
+    fn bad_colours_are_rejected() {
+        assert_eq!(Palette::parse("000000,FFFFF").err().unwrap(), "FFFFF is not a colour, expected RRGGBB in hex");
+        assert_eq!(Palette::parse("000000,GGGGGG").err().unwrap(), "GGGGGG is not a colour, expected RRGGBB in hex");
+        assert_eq!(Palette::parse("000000,+FFFFF").err().unwrap(), "+FFFFF is not a colour, expected RRGGBB in hex");
+        assert_eq!(Palette::parse("000000").err().unwrap(),
+                   "unknown palette 000000, expected one of mono, amber, green, lcd, octo or 2 to 4 hex colours");
+        assert!(Palette::parse("000000,111111,222222,333333,444444").is_err());
+        assert!(Palette::parse("sepia").is_err());
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert_eq!(from_config("[display]\nscale = 0\n").err().unwrap(),
+                   "scale expects a whole number from 1 to 64, got 0");
+        assert!(from_config("[display]\nscale = 65\n").is_err());
+        assert_eq!(from_config("[display]\nfullscreen = maybe\n").err().unwrap(),
+                   "fullscreen expects true or false, got maybe");
+        assert!(from_config("[display]\npersistence = glow\n").is_err());
+        assert_eq!(from_config("[display]\ndecay = 0\n").err().unwrap(),
+                   "decay expects a fraction above 0 and up to 1, got 0");
+        assert!(from_config("[display]\ndecay = 1.5\n").is_err());
+    }
+
+    #[test]
+    fn persistence_and_decay() {
+        let settings = from_config("[display]\npersistence = fade\ndecay = 1\n").unwrap();
+        assert_eq!(settings.persistence, Persistence::Fade);
+        assert_eq!(settings.decay, 1.0);
+    }
+}
@@ -0,0 +1,45 @@
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultKind {
+    // CALL with all stack entries in use
+    StackOverflow,
+
+    // RET with an empty stack
+    StackUnderflow,
+
+    // An access touched this address, which lies outside RAM
+    MemoryOutOfBounds(usize),
+
+    // The opcode does not decode to any instruction
+    InvalidOpcode,
+}
+
+// Raised instead of panicking when a program does something the CPU cannot
+// carry out. The program counter is left pointing at the faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuFault {
+    pub pc: u16,
+    pub opcode: u16,
+    pub kind: FaultKind,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaultKind::StackOverflow => write!(f, "stack overflow"),
+            FaultKind::StackUnderflow => write!(f, "stack underflow"),
+            FaultKind::MemoryOutOfBounds(addr) => write!(f, "memory access out of bounds at {:04X}", addr),
+            FaultKind::InvalidOpcode => write!(f, "invalid opcode"),
+        }
+    }
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (PC {:04X}, opcode {:04X})", self.kind, self.pc, self.opcode)
+    }
+}
+
+impl error::Error for CpuFault {}
@@ -115,3 +115,200 @@ pub fn get_fonts() -> Vec<u8> {
         0x80,
     ];
 }
+
+// Define the binary data for the SUPER-CHIP 8x10 fonts
+pub fn get_large_fonts() -> Vec<u8> {
+    return vec![
+        // 0
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+
+        // 1
+        0x18,
+        0x78,
+        0x78,
+        0x18,
+        0x18,
+        0x18,
+        0x18,
+        0x18,
+        0xFF,
+        0xFF,
+
+        // 2
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+
+        // 3
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0xFF,
+        0xFF,
+
+        // 4
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0x03,
+        0x03,
+
+        // 5
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0xFF,
+        0xFF,
+
+        // 6
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+
+        // 7
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0x06,
+        0x0C,
+        0x18,
+        0x18,
+        0x18,
+        0x18,
+
+        // 8
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+
+        // 9
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+        0x03,
+        0x03,
+        0xFF,
+        0xFF,
+
+        // A
+        0x7E,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xFF,
+        0xFF,
+        0xC3,
+        0xC3,
+        0xC3,
+
+        // B
+        0xFC,
+        0xFC,
+        0xC3,
+        0xC3,
+        0xFC,
+        0xFC,
+        0xC3,
+        0xC3,
+        0xFC,
+        0xFC,
+
+        // C
+        0x3C,
+        0xFF,
+        0xC3,
+        0xC0,
+        0xC0,
+        0xC0,
+        0xC0,
+        0xC3,
+        0xFF,
+        0x3C,
+
+        // D
+        0xFC,
+        0xFE,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xC3,
+        0xFE,
+        0xFC,
+
+        // E
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+
+        // F
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xFF,
+        0xFF,
+        0xC0,
+        0xC0,
+        0xC0,
+        0xC0,
+    ];
+}
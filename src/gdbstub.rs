@@ -0,0 +1,529 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use cpu::{CPU, StepOutcome};
+use debugger::{WatchKind, Watchpoint};
+use fault::{CpuFault, FaultKind};
+use interconnect::{AccessKind, MemoryAccess};
+
+// Signals reported to the debugger when execution stops
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Register numbers in the g packet and target description: V0-VF, I, PC,
+// SP, DT, ST, then the 16 stack entries. Values are big endian like the
+// CHIP-8 itself.
+const NUM_GPR: usize = 16;
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_STACK: usize = 21;
+const STACK_SIZE: usize = 16;
+const NUM_REGS: usize = REG_STACK + STACK_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunState {
+    Running,
+    Paused,
+    Stepping,
+}
+
+// A server for the GDB remote serial protocol, so GDB and other front ends
+// that speak it can drive the CPU over TCP. Like the Debugger, Chip8
+// consults it around every instruction, and it is polled once a frame
+// without blocking so the window stays alive while a client is connected.
+pub struct GdbStub {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+
+    // Bytes received that don't make up a whole packet yet
+    input: Vec<u8>,
+
+    // Set once the client asked to stop acknowledging packets
+    no_ack: bool,
+
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    state: RunState,
+
+    // Reported when the client asks why execution stopped
+    last_stop: String,
+
+    // A breakpoint here doesn't stop the first instruction after resuming
+    resume_pc: Option<u16>,
+}
+
+impl GdbStub {
+    // Listen on a localhost port, 0 picks a free one. Execution starts paused
+    // so a client can connect before the program runs.
+    pub fn bind(port: u16) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbStub {
+            listener: listener,
+            stream: None,
+            input: Vec::new(),
+            no_ack: false,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            state: RunState::Paused,
+            last_stop: stop_signal(SIGTRAP),
+            resume_pc: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state == RunState::Paused
+    }
+
+    // Called before each instruction, returns true if execution should stop
+    // before it instead
+    pub fn before_step(&mut self, cpu: &CPU) -> bool {
+        let pc = cpu.reg_pc();
+        let resuming = self.resume_pc.take() == Some(pc);
+        // An Fx0A that's waiting stopped at its breakpoint when it started
+        if !resuming && !cpu.is_waiting_for_key() && self.breakpoints.contains(&pc) {
+            self.stop(stop_signal(SIGTRAP));
+            return true;
+        }
+        false
+    }
+
+    // Called after each instruction with the memory it accessed
+    pub fn after_step(&mut self, outcome: StepOutcome, accesses: &[MemoryAccess]) {
+        match outcome {
+            StepOutcome::Faulted(fault) => return self.fault(fault),
+            StepOutcome::Halted => return self.stop("W00".to_string()),
+            _ => {},
+        }
+
+        for access in accesses {
+            if let Some(watchpoint) = self.watchpoints.iter().find(|watchpoint| watchpoint.matches(access)) {
+                let name = match (watchpoint.kind, access.kind) {
+                    (WatchKind::ReadWrite, _) => "awatch",
+                    (_, AccessKind::Read) => "rwatch",
+                    (_, AccessKind::Write) => "watch",
+                };
+                let addr = if access.addr > watchpoint.start { access.addr } else { watchpoint.start };
+                let reply = format!("T{:02x}{}:{:x};", SIGTRAP, name, addr);
+                return self.stop(reply);
+            }
+        }
+
+        if self.state == RunState::Stepping {
+            self.stop(stop_signal(SIGTRAP));
+        }
+    }
+
+    pub fn fault(&mut self, fault: CpuFault) {
+        let signal = match fault.kind {
+            FaultKind::InvalidOpcode => SIGILL,
+            _ => SIGSEGV,
+        };
+        self.stop(stop_signal(signal));
+    }
+
+    // Nobody would be there to resume, so faults and halts without a client
+    // end the run as usual
+    fn stop(&mut self, reply: String) {
+        if self.stream.is_none() {
+            return;
+        }
+        self.state = RunState::Paused;
+        self.send(&reply);
+        self.last_stop = reply;
+    }
+
+    // Accept a client and carry out the packets it sent since the last call.
+    // Returns true if the client asked to kill the program.
+    pub fn process(&mut self, cpu: &mut CPU) -> bool {
+        if self.stream.is_none() {
+            self.accept();
+        }
+        self.receive();
+
+        loop {
+            // Acknowledgements need no answer
+            while !self.input.is_empty() && (self.input[0] == b'+' || self.input[0] == b'-') {
+                self.input.remove(0);
+            }
+            if self.input.is_empty() {
+                return false;
+            }
+
+            // Ctrl-C from the client
+            if self.input[0] == 0x03 {
+                self.input.remove(0);
+                if !self.is_paused() {
+                    self.stop(stop_signal(SIGINT));
+                }
+                continue;
+            }
+
+            if self.input[0] != b'$' {
+                self.input.remove(0);
+                continue;
+            }
+            let end = match self.input.iter().position(|&byte| byte == b'#') {
+                Some(end) if end + 2 < self.input.len() => end,
+                _ => return false,
+            };
+            let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let checksum = ::std::str::from_utf8(&packet[end + 1..]).ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if checksum != Some(checksum_of(data)) {
+                if !self.no_ack {
+                    self.send_raw(b"-");
+                }
+                continue;
+            }
+            if !self.no_ack {
+                self.send_raw(b"+");
+            }
+            // Every packet handled is plain ASCII, anything else is rejected
+            // rather than sliced apart
+            match ::std::str::from_utf8(data) {
+                Ok(packet) if packet.is_ascii() => if self.handle_packet(packet, cpu) {
+                    return true;
+                },
+                _ => self.send("E01"),
+            }
+        }
+    }
+
+    fn accept(&mut self) {
+        if let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                let _ = stream.set_nodelay(true);
+                self.stream = Some(stream);
+                self.input.clear();
+                self.no_ack = false;
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut buffer = [0; 4096];
+        loop {
+            let result = match self.stream {
+                Some(ref mut stream) => stream.read(&mut buffer),
+                None => return,
+            };
+            match result {
+                Ok(0) => return self.disconnect(),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) => return self.disconnect(),
+            }
+        }
+    }
+
+    // Without a client there is nobody to resume execution, so carry on
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.input.clear();
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.state = RunState::Running;
+    }
+
+    // Returns true for kill
+    fn handle_packet(&mut self, packet: &str, cpu: &mut CPU) -> bool {
+        let (command, args) = packet.split_at(if packet.is_empty() { 0 } else { 1 });
+        let reply = match command {
+            "?" => self.last_stop.clone(),
+            "g" => (0..NUM_REGS).map(|n| encode_register(cpu, n)).collect(),
+            "G" => {
+                let mut rest = args;
+                for n in 0..NUM_REGS {
+                    let digits = register_size(n) * 2;
+                    if rest.len() < digits {
+                        break;
+                    }
+                    if let Ok(value) = u16::from_str_radix(&rest[..digits], 16) {
+                        set_register(cpu, n, value);
+                    }
+                    rest = &rest[digits..];
+                }
+                "OK".to_string()
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < NUM_REGS => encode_register(cpu, n),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let n = parts.next().and_then(|n| usize::from_str_radix(n, 16).ok());
+                let value = parts.next().and_then(|value| u16::from_str_radix(value, 16).ok());
+                match (n, value) {
+                    (Some(n), Some(value)) if n < NUM_REGS => {
+                        set_register(cpu, n, value);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "m" => match parse_range(args) {
+                Some((addr, len)) if fits_in(addr, len, cpu.interconnect().ram().len()) => {
+                    cpu.interconnect().ram()[addr..addr + len].iter().map(|b| format!("{:02x}", b)).collect()
+                },
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(parse_range);
+                let bytes = parts.next().and_then(decode_hex);
+                match (range, bytes) {
+                    (Some((addr, len)), Some(ref bytes)) if bytes.len() == len
+                        && fits_in(addr, len, cpu.interconnect().ram().len()) => {
+                        cpu.interconnect_mut().ram_mut()[addr..addr + len].copy_from_slice(bytes);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    cpu.set_reg_pc(addr);
+                }
+                self.state = if command == "c" { RunState::Running } else { RunState::Stepping };
+                self.resume_pc = Some(cpu.reg_pc());
+                // The reply is sent when execution stops again
+                return false;
+            },
+            "Z" | "z" => self.breakpoint_packet(command == "Z", args),
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return false;
+            },
+            "k" => return true,
+            "H" => "OK".to_string(),
+            "T" => "OK".to_string(),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+        self.send(&reply);
+        if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+        }
+        false
+    }
+
+    // Z and z, insert and remove. Types 0 and 1 are breakpoints, 2 to 4 are
+    // write, read and access watchpoints.
+    fn breakpoint_packet(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let addr = parts.next().and_then(|addr| usize::from_str_radix(addr, 16).ok());
+        let len = parts.next().and_then(|len| usize::from_str_radix(len, 16).ok());
+        let (addr, len) = match (addr, len) {
+            (Some(addr), Some(len)) => (addr, len),
+            _ => return "E01".to_string(),
+        };
+
+        let watch_kind = match kind {
+            Some("0") | Some("1") => {
+                if insert {
+                    self.breakpoints.insert(addr as u16);
+                } else {
+                    self.breakpoints.remove(&(addr as u16));
+                }
+                return "OK".to_string();
+            },
+            Some("2") => WatchKind::Write,
+            Some("3") => WatchKind::Read,
+            Some("4") => WatchKind::ReadWrite,
+            _ => return String::new(),
+        };
+        let end = match addr.checked_add(if len > 0 { len - 1 } else { 0 }) {
+            Some(end) => end,
+            None => return "E01".to_string(),
+        };
+        let watchpoint = Watchpoint {
+            start: addr,
+            end: end,
+            kind: watch_kind,
+        };
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else {
+            self.watchpoints.retain(|existing| *existing != watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            return match parse_range(range) {
+                Some((offset, _)) if offset >= xml.len() => "l".to_string(),
+                Some((offset, len)) if len >= xml.len() - offset => format!("l{}", &xml[offset..]),
+                Some((offset, len)) => format!("m{}", &xml[offset..offset + len]),
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for &byte in data.as_bytes() {
+            if byte == b'#' || byte == b'$' || byte == b'}' || byte == b'*' {
+                packet.push(b'}');
+                packet.push(byte ^ 0x20);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = checksum_of(&packet[1..]);
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        self.send_raw(&packet);
+    }
+
+    // Replies are small, so wait for them to be written rather than queueing them
+    fn send_raw(&mut self, data: &[u8]) {
+        let failed = match self.stream {
+            Some(ref mut stream) => {
+                let _ = stream.set_nonblocking(false);
+                let result = stream.write_all(data);
+                let _ = stream.set_nonblocking(true);
+                result.is_err()
+            },
+            None => false,
+        };
+        if failed {
+            self.disconnect();
+        }
+    }
+}
+
+fn stop_signal(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// addr,len in hex
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = parts.next().and_then(|addr| usize::from_str_radix(addr, 16).ok());
+    let len = parts.next().and_then(|len| usize::from_str_radix(len, 16).ok());
+    match (addr, len) {
+        (Some(addr), Some(len)) => Some((addr, len)),
+        _ => None,
+    }
+}
+
+// Whether addr..addr + len lies within 0..size, without overflowing on
+// whatever numbers the client sends
+fn fits_in(addr: usize, len: usize, size: usize) -> bool {
+    match addr.checked_add(len) {
+        Some(end) => end <= size,
+        None => false,
+    }
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits.as_bytes().chunks(2)
+        .map(|pair| match (hex_digit(pair[0]), hex_digit(pair[1])) {
+            (Some(high), Some(low)) => Some(high << 4 | low),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        REG_I | REG_PC => 2,
+        _ if n >= REG_STACK => 2,
+        _ => 1,
+    }
+}
+
+fn encode_register(cpu: &CPU, n: usize) -> String {
+    let value = match n {
+        _ if n < NUM_GPR => cpu.reg_gpr()[n] as u16,
+        REG_I => cpu.reg_i(),
+        REG_PC => cpu.reg_pc(),
+        REG_SP => cpu.reg_sp() as u16,
+        REG_DT => cpu.reg_dt() as u16,
+        REG_ST => cpu.reg_st() as u16,
+        _ => cpu.stack()[n - REG_STACK],
+    };
+    if register_size(n) == 2 {
+        format!("{:04x}", value)
+    } else {
+        format!("{:02x}", value)
+    }
+}
+
+fn set_register(cpu: &mut CPU, n: usize, value: u16) {
+    match n {
+        _ if n < NUM_GPR => cpu.set_reg_gpr(n, value as u8),
+        REG_I => cpu.set_reg_i(value),
+        REG_PC => cpu.set_reg_pc(value),
+        REG_SP => cpu.set_reg_sp(value as u8),
+        REG_DT => cpu.set_reg_dt(value as u8),
+        REG_ST => cpu.set_reg_st(value as u8),
+        _ => cpu.set_stack_entry(n - REG_STACK, value),
+    }
+}
+
+// Describes the register set to clients that ask for it
+fn target_xml() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n\
+                                <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+                                <target version=\"1.0\">\n\
+                                <feature name=\"org.chip8.core\">\n");
+    for n in 0..NUM_REGS {
+        let (name, kind) = match n {
+            _ if n < NUM_GPR => (format!("v{:x}", n), "uint8"),
+            REG_I => ("i".to_string(), "data_ptr"),
+            REG_PC => ("pc".to_string(), "code_ptr"),
+            REG_SP => ("sp".to_string(), "uint8"),
+            REG_DT => ("dt".to_string(), "uint8"),
+            REG_ST => ("st".to_string(), "uint8"),
+            _ => (format!("stack{}", n - REG_STACK), "code_ptr"),
+        };
+        xml.push_str(&format!("<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+                              name, register_size(n) * 8, kind, n));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
@@ -0,0 +1,68 @@
+use display::Display;
+use audio::AudioPattern;
+use platform::{VideoSink, Beeper, InputSource};
+
+// A platform with no window, audio device or keyboard. Keys are pressed and
+// released programmatically, which makes it suitable for automated tests.
+pub struct HeadlessPlatform {
+    key_state: [bool; 16],
+
+    // Number of times a changed framebuffer was presented
+    pub frames_presented: u64,
+
+    pub beeping: bool,
+
+    pub audio: AudioPattern,
+}
+
+impl HeadlessPlatform {
+    pub fn new() -> HeadlessPlatform {
+        HeadlessPlatform {
+            key_state: [false; 16],
+            frames_presented: 0,
+            beeping: false,
+            audio: AudioPattern::new(),
+        }
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.key_state[key as usize] = true;
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.key_state[key as usize] = false;
+    }
+}
+
+impl Default for HeadlessPlatform {
+    fn default() -> HeadlessPlatform {
+        HeadlessPlatform::new()
+    }
+}
+
+impl VideoSink for HeadlessPlatform {
+    fn present(&mut self, _display_state: &Display) {
+        self.frames_presented += 1;
+    }
+}
+
+impl Beeper for HeadlessPlatform {
+    fn start_beep(&mut self) {
+        self.beeping = true;
+    }
+
+    fn stop_beep(&mut self) {
+        self.beeping = false;
+    }
+
+    fn set_pattern(&mut self, pattern: &AudioPattern) {
+        self.audio = *pattern;
+    }
+}
+
+impl InputSource for HeadlessPlatform {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        *key_state = self.key_state;
+        false
+    }
+}
@@ -0,0 +1,361 @@
+use std::error;
+use std::fmt;
+
+use variant::Variant;
+
+// A decoded Chip 8 instruction. Register operands are indexes into V0-VF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // 0000 - Stop the interpreter
+    Halt,
+    // 0nnn - SYS addr
+    Sys(u16),
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 1nnn - JP addr
+    Jp(u16),
+    // 2nnn - CALL addr
+    Call(u16),
+    // 3xkk - SE Vx, byte
+    SeByte(usize, u8),
+    // 4xkk - SNE Vx, byte
+    SneByte(usize, u8),
+    // 5xy0 - SE Vx, Vy
+    SeReg(usize, usize),
+    // 6xkk - LD Vx, byte
+    LdByte(usize, u8),
+    // 7xkk - ADD Vx, byte
+    AddByte(usize, u8),
+    // 8xy0 - LD Vx, Vy
+    LdReg(usize, usize),
+    // 8xy1 - OR Vx, Vy
+    Or(usize, usize),
+    // 8xy2 - AND Vx, Vy
+    And(usize, usize),
+    // 8xy3 - XOR Vx, Vy
+    Xor(usize, usize),
+    // 8xy4 - ADD Vx, Vy
+    AddReg(usize, usize),
+    // 8xy5 - SUB Vx, Vy
+    Sub(usize, usize),
+    // 8xy6 - SHR Vx, Vy
+    Shr(usize, usize),
+    // 8xy7 - SUBN Vx, Vy
+    Subn(usize, usize),
+    // 8xyE - SHL Vx, Vy
+    Shl(usize, usize),
+    // 9xy0 - SNE Vx, Vy
+    SneReg(usize, usize),
+    // Annn - LD I, addr
+    LdI(u16),
+    // Bnnn - JP V0, addr
+    JpV0(u16),
+    // Cxkk - RND Vx, byte
+    Rnd(usize, u8),
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw(usize, usize, u8),
+    // Ex9E - SKP Vx
+    Skp(usize),
+    // ExA1 - SKNP Vx
+    Sknp(usize),
+    // Fx07 - LD Vx, DT
+    LdVxDt(usize),
+    // Fx0A - LD Vx, K
+    LdVxK(usize),
+    // Fx15 - LD DT, Vx
+    LdDtVx(usize),
+    // Fx18 - LD ST, Vx
+    LdStVx(usize),
+    // Fx1E - ADD I, Vx
+    AddIVx(usize),
+    // Fx29 - LD F, Vx
+    LdFVx(usize),
+    // Fx33 - LD B, Vx
+    LdBVx(usize),
+    // Fx55 - LD [I], Vx
+    LdIVx(usize),
+    // Fx65 - LD Vx, [I]
+    LdVxI(usize),
+
+    // SUPER-CHIP
+
+    // 00Cn - SCD nibble
+    ScrollDown(u8),
+    // 00FB - SCR
+    ScrollRight,
+    // 00FC - SCL
+    ScrollLeft,
+    // 00FD - EXIT
+    Exit,
+    // 00FE - LOW
+    Lores,
+    // 00FF - HIGH
+    Hires,
+    // Fx30 - LD HF, Vx
+    LdHfVx(usize),
+    // Fx75 - LD R, Vx
+    LdRVx(usize),
+    // Fx85 - LD Vx, R
+    LdVxR(usize),
+
+    // XO-CHIP
+
+    // 00Dn - SCU nibble
+    ScrollUp(u8),
+    // 5xy2 - LD [I], Vx-Vy
+    SaveRange(usize, usize),
+    // 5xy3 - LD Vx-Vy, [I]
+    LoadRange(usize, usize),
+    // F000 nnnn - LD I, long addr
+    LdILong(u16),
+    // Fn01 - PLANE n
+    Plane(u8),
+    // F002 - AUDIO
+    Audio,
+    // Fx3A - PITCH Vx
+    Pitch(usize),
+}
+
+impl Instruction {
+    // The first variant whose interpreter understands this instruction
+    pub fn variant(&self) -> Variant {
+        use self::Instruction::*;
+
+        match *self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Lores | Hires |
+            LdHfVx(_) | LdRVx(_) | LdVxR(_) => Variant::SuperChip,
+            ScrollUp(_) | SaveRange(..) | LoadRange(..) | LdILong(_) | Plane(_) |
+            Audio | Pitch(_) => Variant::XoChip,
+            _ => Variant::Chip8,
+        }
+    }
+
+    // Length in bytes, F000 nnnn is the only four byte instruction
+    pub fn size(&self) -> u16 {
+        match *self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // The opcode with its operands as placeholders, e.g. 8xy4 for every ADD
+    // Vx, Vy, for grouping instructions of the same kind
+    pub fn pattern(&self) -> &'static str {
+        use self::Instruction::*;
+
+        match *self {
+            Halt => "0000",
+            Sys(_) => "0nnn",
+            Cls => "00E0",
+            Ret => "00EE",
+            Jp(_) => "1nnn",
+            Call(_) => "2nnn",
+            SeByte(..) => "3xkk",
+            SneByte(..) => "4xkk",
+            SeReg(..) => "5xy0",
+            LdByte(..) => "6xkk",
+            AddByte(..) => "7xkk",
+            LdReg(..) => "8xy0",
+            Or(..) => "8xy1",
+            And(..) => "8xy2",
+            Xor(..) => "8xy3",
+            AddReg(..) => "8xy4",
+            Sub(..) => "8xy5",
+            Shr(..) => "8xy6",
+            Subn(..) => "8xy7",
+            Shl(..) => "8xyE",
+            SneReg(..) => "9xy0",
+            LdI(_) => "Annn",
+            JpV0(_) => "Bnnn",
+            Rnd(..) => "Cxkk",
+            Drw(..) => "Dxyn",
+            Skp(_) => "Ex9E",
+            Sknp(_) => "ExA1",
+            LdVxDt(_) => "Fx07",
+            LdVxK(_) => "Fx0A",
+            LdDtVx(_) => "Fx15",
+            LdStVx(_) => "Fx18",
+            AddIVx(_) => "Fx1E",
+            LdFVx(_) => "Fx29",
+            LdBVx(_) => "Fx33",
+            LdIVx(_) => "Fx55",
+            LdVxI(_) => "Fx65",
+            ScrollDown(_) => "00Cn",
+            ScrollRight => "00FB",
+            ScrollLeft => "00FC",
+            Exit => "00FD",
+            Lores => "00FE",
+            Hires => "00FF",
+            LdHfVx(_) => "Fx30",
+            LdRVx(_) => "Fx75",
+            LdVxR(_) => "Fx85",
+            ScrollUp(_) => "00Dn",
+            SaveRange(..) => "5xy2",
+            LoadRange(..) => "5xy3",
+            LdILong(_) => "F000",
+            Plane(_) => "Fn01",
+            Audio => "F002",
+            Pitch(_) => "Fx3A",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl error::Error for DecodeError {}
+
+// Decode a raw opcode without executing it. next is the word following it,
+// which is only used as the address of F000 nnnn.
+pub fn decode(instr: u16, next: u16) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let nnn = instr & 0x0FFF;
+    let x = ((instr >> 8) & 0xF) as usize;
+    let y = ((instr >> 4) & 0xF) as usize;
+    let n = (instr & 0xF) as u8;
+    let kk = (instr & 0xFF) as u8;
+
+    let instruction = match instr >> 12 {
+        0x0 => match nnn {
+            0x000 => Halt,
+            0x0E0 => Cls,
+            0x0EE => Ret,
+            0x0C0..=0x0CF => ScrollDown(n),
+            0x0D0..=0x0DF => ScrollUp(n),
+            0x0FB => ScrollRight,
+            0x0FC => ScrollLeft,
+            0x0FD => Exit,
+            0x0FE => Lores,
+            0x0FF => Hires,
+            _ => Sys(nnn),
+        },
+        0x1 => Jp(nnn),
+        0x2 => Call(nnn),
+        0x3 => SeByte(x, kk),
+        0x4 => SneByte(x, kk),
+        0x5 => match n {
+            0x0 => SeReg(x, y),
+            0x2 => SaveRange(x, y),
+            0x3 => LoadRange(x, y),
+            _ => return Err(DecodeError { opcode: instr }),
+        },
+        0x6 => LdByte(x, kk),
+        0x7 => AddByte(x, kk),
+        0x8 => match n {
+            0x0 => LdReg(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => AddReg(x, y),
+            0x5 => Sub(x, y),
+            0x6 => Shr(x, y),
+            0x7 => Subn(x, y),
+            0xE => Shl(x, y),
+            _ => return Err(DecodeError { opcode: instr }),
+        },
+        0x9 if n == 0x0 => SneReg(x, y),
+        0xA => LdI(nnn),
+        0xB => JpV0(nnn),
+        0xC => Rnd(x, kk),
+        0xD => Drw(x, y, n),
+        0xE => match kk {
+            0x9E => Skp(x),
+            0xA1 => Sknp(x),
+            _ => return Err(DecodeError { opcode: instr }),
+        },
+        0xF => match kk {
+            0x00 if x == 0 => LdILong(next),
+            0x01 => Plane(x as u8),
+            0x02 if x == 0 => Audio,
+            0x07 => LdVxDt(x),
+            0x0A => LdVxK(x),
+            0x15 => LdDtVx(x),
+            0x18 => LdStVx(x),
+            0x1E => AddIVx(x),
+            0x29 => LdFVx(x),
+            0x30 => LdHfVx(x),
+            0x3A => Pitch(x),
+            0x33 => LdBVx(x),
+            0x55 => LdIVx(x),
+            0x65 => LdVxI(x),
+            0x75 => LdRVx(x),
+            0x85 => LdVxR(x),
+            _ => return Err(DecodeError { opcode: instr }),
+        },
+        _ => return Err(DecodeError { opcode: instr }),
+    };
+    Ok(instruction)
+}
+
+// Disassembly in the usual Cowgod mnemonics
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            Halt => write!(f, "HALT"),
+            Sys(addr) => write!(f, "SYS {:03X}", addr),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jp(addr) => write!(f, "JP {:03X}", addr),
+            Call(addr) => write!(f, "CALL {:03X}", addr),
+            SeByte(x, kk) => write!(f, "SE V{:X}, {:02X}", x, kk),
+            SneByte(x, kk) => write!(f, "SNE V{:X}, {:02X}", x, kk),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdByte(x, kk) => write!(f, "LD V{:X}, {:02X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, {:02X}", x, kk),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, {:03X}", addr),
+            JpV0(addr) => write!(f, "JP V0, {:03X}", addr),
+            Rnd(x, kk) => write!(f, "RND V{:X}, {:02X}", x, kk),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {:X}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            AddIVx(x) => write!(f, "ADD I, V{:X}", x),
+            LdFVx(x) => write!(f, "LD F, V{:X}", x),
+            LdBVx(x) => write!(f, "LD B, V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {:X}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            LdHfVx(x) => write!(f, "LD HF, V{:X}", x),
+            LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            LdVxR(x) => write!(f, "LD V{:X}, R", x),
+            ScrollUp(n) => write!(f, "SCU {:X}", n),
+            SaveRange(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LoadRange(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LdILong(addr) => write!(f, "LD I, {:04X}", addr),
+            Plane(n) => write!(f, "PLANE {:X}", n),
+            Audio => write!(f, "AUDIO"),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+        }
+    }
+}
@@ -1,331 +1,405 @@
-use super::fonts::get_fonts;
-use byteorder::{BigEndian, ByteOrder};
-use rand;
-use rand::Rng;
-
-use sdl2;
-use sdl2::pixels::Color;
-use sdl2::audio::AudioCallback;
-use sdl2::audio::AudioSpecDesired;
-
-const RAM_SIZE: usize = 4096;
-
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::io::{Read, Write};
+
+use super::fonts::{get_fonts, get_large_fonts};
+use display::{Display, NUM_PLANES};
+use audio::{AudioPattern, PATTERN_SIZE};
+use platform::{VideoSink, Beeper, InputSource};
+use fault::FaultKind;
+use rng::{Rng, RngMode};
+use savestate::SaveStateError;
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+
+// Enough for XO-CHIP, other variants only address the first 4 KiB
+pub const RAM_SIZE: usize = 65536;
+pub const CLASSIC_RAM_SIZE: usize = 4096;
+pub const PROGRAM_START: usize = 512;
+
+// The SUPER-CHIP 8x10 font sits right after the regular 4x5 one
+pub const LARGE_FONT_START: usize = 80;
+
+// A program with more bytes than fit in RAM after PROGRAM_START
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RomTooLarge {
+    pub len: usize,
+    pub max: usize,
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        //Generate a square wave
-        for x in out.iter_mut() {
-            *x = match self.phase {
-                0.0...0.5 => self.volume,
-                _ => -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rom is {} bytes, only {} fit in memory", self.len, self.max)
     }
 }
 
+impl error::Error for RomTooLarge {}
 
-pub struct Interconnect {
-    // renderer
-    renderer: sdl2::render::Renderer<'static>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
 
-    // audio
-    audio_device: sdl2::audio::AudioDevice<SquareWave>,
+// A data access an instruction made to RAM. Instruction fetches are not included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub len: usize,
+    pub kind: AccessKind,
+}
 
-    // events
-    event_pump: sdl2::EventPump,
-    
+pub struct Interconnect {
     ram: [u8; RAM_SIZE],
 
+    // How much of ram the program can address, depends on the variant
+    memory_size: usize,
+
     pub halt: bool,
 
-    display_state: [[bool; 32]; 64],
+    display_state: Display,
+
+    // Set whenever display_state changes, cleared once it has been presented
+    display_dirty: bool,
 
     key_state: [bool; 16],
+
+    audio: AudioPattern,
+
+    // Set whenever audio changes, cleared once the beeper has been told
+    audio_dirty: bool,
+
+    rng: Rng,
+
+    // Data accesses since the last take_accesses, only kept while enabled
+    access_log: Option<Vec<MemoryAccess>>,
 }
 
 impl Interconnect {
     pub fn new(program: Vec<u8>) -> Interconnect {
-        let mut ram: [u8; RAM_SIZE] = [0; RAM_SIZE];
+        let mut interconnect = Interconnect {
+            ram: [0; RAM_SIZE],
+            memory_size: CLASSIC_RAM_SIZE,
+            halt: false,
+            display_state: Display::new(),
+            display_dirty: true,
+            key_state: [false; 16],
+            audio: AudioPattern::new(),
+            audio_dirty: true,
+            rng: Rng::from_entropy(),
+            access_log: None,
+        };
+        // Too big a program is cut short here, Chip8::load_rom is the place
+        // that rejects it
+        let fits = cmp::min(program.len(), CLASSIC_RAM_SIZE - PROGRAM_START);
+        let _ = interconnect.load_program(&program[..fits]);
+        interconnect
+    }
+
+    // Reset RAM to the fonts followed by the program and blank the display.
+    // Nothing is changed if the program doesn't fit in the memory the current
+    // variant can address.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), RomTooLarge> {
+        let max = self.memory_size - PROGRAM_START;
+        if program.len() > max {
+            return Err(RomTooLarge { len: program.len(), max: max });
+        }
+        self.ram = [0; RAM_SIZE];
 
         let mut ram_index = 0;
 
         let fonts = get_fonts();
         for val in fonts {
-            ram[ram_index] = val;
+            self.ram[ram_index] = val;
             ram_index = ram_index + 1;
         }
 
-        ram_index = 512;
+        ram_index = LARGE_FONT_START;
+        for val in get_large_fonts() {
+            self.ram[ram_index] = val;
+            ram_index = ram_index + 1;
+        }
+
+        ram_index = PROGRAM_START;
         for val in program {
-            ram[ram_index] = val;
+            self.ram[ram_index] = *val;
             ram_index = ram_index + 1;
         }
 
-        let sdl_context = sdl2::init().unwrap();
-        let video = sdl_context.video().unwrap();
+        self.halt = false;
+        self.display_state = Display::new();
+        self.display_dirty = true;
+        self.key_state = [false; 16];
+        self.audio = AudioPattern::new();
+        self.audio_dirty = true;
+        Ok(())
+    }
 
-        let window = video.window("Chip 8", 640, 320)
-            .position_centered().opengl()
-            .build().unwrap();
+    // The addressable part of RAM
+    pub fn ram(&self) -> &[u8] {
+        &self.ram[..self.memory_size]
+    }
 
-        let mut renderer = window.renderer()
-            .accelerated()
-            .build().unwrap();
+    // For debuggers, changes made through this are not logged as accesses
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram[..self.memory_size]
+    }
 
-        renderer.set_draw_color(Color::RGB(0, 0, 0));
-        renderer.clear();
-        renderer.present();
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
 
-        // TODO: Setup audio device
-        let audio_system = sdl_context.audio().unwrap();
-        let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1),
-            samples: None,
-        };
+    pub fn set_memory_size(&mut self, memory_size: usize) {
+        self.memory_size = cmp::min(memory_size, RAM_SIZE);
+    }
 
-        let device = audio_system.open_playback(None, &desired_spec, |spec| {
-            SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.25,
-            }
-        }).unwrap();
-
-        let event_pump = sdl_context.event_pump().unwrap();
-       
-        Interconnect {
-            ram: ram,
-            renderer: renderer,
-            event_pump: event_pump,
-            halt: false,
-            display_state: [[false; 32]; 64],
-            key_state: [false; 16],
-            audio_device: device,
+    pub fn display_state(&self) -> &Display {
+        &self.display_state
+    }
+
+    pub fn key_state(&self) -> [bool; 16] {
+        self.key_state
+    }
+
+    pub fn set_key_state(&mut self, key_state: [bool; 16]) {
+        self.key_state = key_state;
+    }
+
+    pub fn handle_events<I: InputSource>(&mut self, input: &mut I) {
+        if input.poll(&mut self.key_state) {
+            self.halt = true;
         }
     }
 
-    pub fn handle_events(&mut self) {
-        for event in self.event_pump.poll_iter() {
-            use sdl2::event::Event::*;
-            use sdl2::keyboard::Keycode::*;
-
-            match event {
-                Quit { .. } => self.halt = true,
-
-                KeyDown { keycode, .. } => match keycode {
-                    Some(Escape) => self.halt = true,
-                    Some(Num0) => self.key_state[0x0] = true,
-                    Some(Num1) => self.key_state[0x1] = true,
-                    Some(Num2) => self.key_state[0x2] = true,
-                    Some(Num3) => self.key_state[0x3] = true,
-                    Some(Num4) => self.key_state[0x4] = true,
-                    Some(Num5) => self.key_state[0x5] = true,
-                    Some(Num6) => self.key_state[0x6] = true,
-                    Some(Num7) => self.key_state[0x7] = true,
-                    Some(Num8) => self.key_state[0x8] = true,
-                    Some(Num9) => self.key_state[0x9] = true,
-                    Some(A) => self.key_state[0xA] = true,
-                    Some(B) => self.key_state[0xB] = true,
-                    Some(C) => self.key_state[0xC] = true,
-                    Some(D) => self.key_state[0xD] = true,
-                    Some(E) => self.key_state[0xE] = true,
-                    Some(F) => self.key_state[0xF] = true,
-                    _ => {}
-                },
-
-                KeyUp { keycode, .. } => match keycode {
-                    Some(Num0) => self.key_state[0x0] = false,
-                    Some(Num1) => self.key_state[0x1] = false,
-                    Some(Num2) => self.key_state[0x2] = false,
-                    Some(Num3) => self.key_state[0x3] = false,
-                    Some(Num4) => self.key_state[0x4] = false,
-                    Some(Num5) => self.key_state[0x5] = false,
-                    Some(Num6) => self.key_state[0x6] = false,
-                    Some(Num7) => self.key_state[0x7] = false,
-                    Some(Num8) => self.key_state[0x8] = false,
-                    Some(Num9) => self.key_state[0x9] = false,
-                    Some(A) => self.key_state[0xA] = false,
-                    Some(B) => self.key_state[0xB] = false,
-                    Some(C) => self.key_state[0xC] = false,
-                    Some(D) => self.key_state[0xD] = false,
-                    Some(E) => self.key_state[0xE] = false,
-                    Some(F) => self.key_state[0xF] = false,
-                    _ => {}
-                },
-
-                _ => {}
-            }
+    // Hand the framebuffer to the video sink if it changed since the last
+    // call, then let it refresh, once per frame
+    pub fn present<V: VideoSink>(&mut self, video: &mut V) {
+        if self.display_dirty {
+            video.present(&self.display_state);
+            self.display_dirty = false;
         }
+        video.refresh();
     }
 
-    pub fn clear_display(&mut self) {
-        self.renderer.set_draw_color(Color::RGB(0, 0, 0));
-        self.renderer.clear();
-        self.renderer.present();
-    }
-
-    fn keycode_from_key(&self, key: u8) -> sdl2::keyboard::Keycode {
-        use sdl2::keyboard::Keycode::*;
-        return match key {
-            0x0 => Num0,
-            0x1 => Num1,
-            0x2 => Num2,
-            0x3 => Num3,
-            0x4 => Num4,
-            0x5 => Num5,
-            0x6 => Num6,
-            0x7 => Num7,
-            0x8 => Num8,
-            0x9 => Num9,
-            0xA => A,
-            0xB => B,
-            0xC => C,
-            0xD => D,
-            0xE => E,
-            0xF => F,
-            _ => Escape,
+    // Tell the beeper about a new pattern or pitch if either changed since the last call
+    pub fn update_audio<B: Beeper>(&mut self, beeper: &mut B) {
+        if self.audio_dirty {
+            beeper.set_pattern(&self.audio);
+            self.audio_dirty = false;
         }
     }
 
-    pub fn wait_for_keypress(&mut self, key: u8) {
-        let keycode_to_match = self.keycode_from_key(key);
-        loop {
-            use sdl2::event::Event::*;
-            use sdl2::keyboard::Keycode::*;
-            let event = self.event_pump.wait_event();
-            match event {
-                Quit { .. } => {
-                    self.halt = true;
-                    break;
-                },
-
-                KeyDown { keycode, .. } => {
-                    match keycode {
-                        Some(Escape) => {
-                            self.halt = true;
-                            break;
-                        },
-                        _ => {
-                            if keycode == Some(keycode_to_match) {
-                                break;
-                            }
-                        }
-                    }
-                },
+    pub fn audio(&self) -> &AudioPattern {
+        &self.audio
+    }
 
-                KeyUp { keycode, .. } => match keycode {
-                    _ => {}
-                },
+    pub fn load_audio_pattern(&mut self, i_addr: usize) -> Result<(), FaultKind> {
+        self.check_range(i_addr, PATTERN_SIZE)?;
+        self.log_access(i_addr, PATTERN_SIZE, AccessKind::Read);
+        self.audio.pattern.copy_from_slice(&self.ram[i_addr..i_addr + PATTERN_SIZE]);
+        self.audio_dirty = true;
+        Ok(())
+    }
 
-                _ => {}
-            }
-        }
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.audio.pitch = pitch;
+        self.audio_dirty = true;
     }
 
-    pub fn start_beep(&mut self) {
-        self.audio_device.resume();
+    pub fn clear_display(&mut self) {
+        self.display_state.clear();
+        self.display_dirty = true;
     }
 
-    pub fn stop_beep(&mut self) {
-        self.audio_device.pause();
+    pub fn set_hires(&mut self, hires: bool) {
+        self.display_state.set_hires(hires);
+        self.display_dirty = true;
     }
 
-    pub fn wait_for_step(&mut self) -> bool {
-        loop {
-            use sdl2::event::Event::*;
-            use sdl2::keyboard::Keycode::*;
-            let event = self.event_pump.wait_event();
-            match event {
-                KeyDown { keycode, .. } => {
-                    match keycode {
-                        Some(S) => {
-                            return false;
-                        },
-                        Some(P) => {
-                            return true;
-                        },
-                        _ => { }
-                    }
-                },
+    pub fn select_planes(&mut self, planes: u8) {
+        self.display_state.set_planes(planes);
+    }
 
-                _ => {}
-            }
+    pub fn scroll_display_up(&mut self, n: usize) {
+        self.display_state.scroll_up(n);
+        self.display_dirty = true;
+    }
+
+    pub fn scroll_display_down(&mut self, n: usize) {
+        self.display_state.scroll_down(n);
+        self.display_dirty = true;
+    }
+
+    pub fn scroll_display_left(&mut self, n: usize) {
+        self.display_state.scroll_left(n);
+        self.display_dirty = true;
+    }
+
+    pub fn scroll_display_right(&mut self, n: usize) {
+        self.display_state.scroll_right(n);
+        self.display_dirty = true;
+    }
+
+    pub fn get_random_value(&mut self) -> u8 {
+        self.rng.next_u8(&self.ram[..PROGRAM_START])
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    pub fn tick_rng(&mut self) {
+        self.rng.tick();
+    }
+
+    // Everything except halt, which records a request from the user rather
+    // than machine state
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> Result<(), SaveStateError> {
+        writer.write_u32::<BigEndian>(self.memory_size as u32)?;
+        writer.write_all(self.ram())?;
+        self.display_state.write_state(writer)?;
+        for pressed in self.key_state.iter() {
+            writer.write_u8(*pressed as u8)?;
+        }
+        writer.write_all(&self.audio.pattern)?;
+        writer.write_u8(self.audio.pitch)?;
+        writer.write_u8(self.rng.mode() as u8)?;
+        writer.write_u64::<BigEndian>(self.rng.state())?;
+        Ok(())
+    }
+
+    pub fn read_state<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveStateError> {
+        let memory_size = reader.read_u32::<BigEndian>()? as usize;
+        if memory_size > RAM_SIZE {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        self.memory_size = memory_size;
+        self.ram = [0; RAM_SIZE];
+        reader.read_exact(&mut self.ram[..memory_size])?;
+        self.display_state.read_state(reader)?;
+        self.display_dirty = true;
+        for pressed in self.key_state.iter_mut() {
+            *pressed = reader.read_u8()? != 0;
         }
+        reader.read_exact(&mut self.audio.pattern)?;
+        self.audio.pitch = reader.read_u8()?;
+        self.audio_dirty = true;
+        let rng_mode = match RngMode::from_index(reader.read_u8()?) {
+            Some(mode) => mode,
+            None => return Err(SaveStateError::Invalid("rng mode")),
+        };
+        self.rng = Rng::with_mode(rng_mode, reader.read_u64::<BigEndian>()?);
+        Ok(())
     }
 
-    pub fn get_random_value(&self) -> u8 {
-        let mut rng = rand::thread_rng();
-        return rng.gen::<u8>();
+    // Fail unless every address from addr to addr + len - 1 is inside RAM
+    pub fn check_range(&self, addr: usize, len: usize) -> Result<(), FaultKind> {
+        if addr + len > self.memory_size {
+            return Err(FaultKind::MemoryOutOfBounds(cmp::max(addr, self.memory_size)));
+        }
+        Ok(())
     }
 
     #[inline(always)]
-    pub fn read_word(&self, addr: u16) -> u16 {
-        BigEndian::read_u16(&self.ram[addr as usize..])
+    pub fn read_word(&self, addr: u16) -> Result<u16, FaultKind> {
+        self.check_range(addr as usize, 2)?;
+        Ok(BigEndian::read_u16(&self.ram[addr as usize..]))
     }
 
-    pub fn write_to_addr(&mut self, addr: usize, val: u8) {
+    pub fn write_to_addr(&mut self, addr: usize, val: u8) -> Result<(), FaultKind> {
+        self.check_range(addr, 1)?;
+        self.log_access(addr, 1, AccessKind::Write);
         self.ram[addr] = val;
+        Ok(())
     }
 
-    pub fn get_from_addr(&self, addr: usize) -> u8 {
-        return self.ram[addr];
+    pub fn get_from_addr(&mut self, addr: usize) -> Result<u8, FaultKind> {
+        self.check_range(addr, 1)?;
+        self.log_access(addr, 1, AccessKind::Read);
+        Ok(self.ram[addr])
     }
 
-    fn write_byte_to_display(&mut self, addr: usize, x_loc: usize, y_loc: usize) -> bool {
-        let byte = self.ram[addr] as u8;
-        println!("Writing byte {0:b} at location {1} {2}", byte, x_loc, y_loc);
-        let mut overrode = false;
-        for i in 0..8 {
-            let bit = (byte << i) >> 7;
-            let x_pos = (x_loc + i) % 64;
-            let cur_val = self.display_state[x_pos][y_loc];
-            if bit == 1 && cur_val == true {
-                self.display_state[x_pos][y_loc] = false;
-                overrode = true;
-            } else {
-                let val = bit == 1 || cur_val;
-                self.display_state[x_pos][y_loc] = val;
-            }
+    // Start or stop keeping a log of the data accesses instructions make
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn is_access_logging(&self) -> bool {
+        self.access_log.is_some()
+    }
+
+    // The accesses made since the last call
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        match self.access_log {
+            Some(ref mut log) => mem::take(log),
+            None => Vec::new(),
+        }
+    }
+
+    fn log_access(&mut self, addr: usize, len: usize, kind: AccessKind) {
+        if let Some(ref mut log) = self.access_log {
+            log.push(MemoryAccess { addr: addr, len: len, kind: kind });
         }
+    }
 
-        return overrode;
+    fn write_byte_to_display(&mut self, addr: usize, x_loc: usize, y_loc: usize, plane: u8, clip: bool) -> bool {
+        let byte = self.ram[addr] as u8;
+        self.display_state.draw_byte(byte, x_loc, y_loc, plane, clip)
     }
 
-    pub fn display_bytes(&mut self, num_bytes: u8, i_addr: usize, x_loc: usize, y_loc: usize) -> bool {
+    // Draw a sprite bytes_per_row * 8 pixels wide and rows high into every
+    // selected plane. The starting position always wraps onto the screen, the
+    // parts of the sprite hanging off the edge are either clipped or wrapped.
+    // With both planes selected the second plane's data follows the first's.
+    fn display_sprite(&mut self, rows: usize, bytes_per_row: usize, i_addr: usize, x_loc: usize, y_loc: usize, clip: bool) -> Result<bool, FaultKind> {
+        let sprite_size = rows * bytes_per_row;
+        let selected = self.display_state.planes();
+        self.check_range(i_addr, sprite_size * selected.count_ones() as usize)?;
+        self.log_access(i_addr, sprite_size * selected.count_ones() as usize, AccessKind::Read);
+
+        let width = self.display_state.width();
+        let height = self.display_state.height();
+        let x_loc = x_loc % width;
+        let y_loc = y_loc % height;
         let mut overrode = false;
-        for i in 0..num_bytes as usize {
-            overrode = self.write_byte_to_display(i_addr + i, x_loc, (y_loc + i) % 32) || overrode;
-        }
-        self.render_display_state();
-        return overrode;
-    }
-
-    fn render_display_state(&mut self) {
-        self.renderer.set_draw_color(Color::RGB(0, 0, 0));
-        self.renderer.clear();
-        self.renderer.set_draw_color(Color::RGB(255, 255, 255));
-        let mut rects: Vec<sdl2::rect::Rect> = Vec::new();
-        for x in 0..64 {
-            for y in 0..32 {
-                if self.display_state[x][y] {
-                    rects.push(sdl2::rect::Rect::new((x as i32) * 10, (y as i32) * 10, 10, 10));
+        let mut sprite_addr = i_addr;
+        for plane in (0..NUM_PLANES).map(|n| 1 << n).filter(|plane| selected & plane != 0) {
+            for i in 0..rows {
+                if clip && y_loc + i >= height {
+                    break;
+                }
+                for j in 0..bytes_per_row {
+                    let x_pos = x_loc + j * 8;
+                    if clip && x_pos >= width {
+                        break;
+                    }
+                    let addr = sprite_addr + i * bytes_per_row + j;
+                    overrode = self.write_byte_to_display(addr, x_pos % width, (y_loc + i) % height, plane, clip) || overrode;
                 }
             }
+            sprite_addr += sprite_size;
         }
-        self.renderer.fill_rects(&rects[..]);
-        self.renderer.present();
+        self.display_dirty = true;
+        Ok(overrode)
+    }
+
+    // Draw an 8 pixel wide sprite num_bytes rows high
+    pub fn display_bytes(&mut self, num_bytes: u8, i_addr: usize, x_loc: usize, y_loc: usize, clip: bool) -> Result<bool, FaultKind> {
+        self.display_sprite(num_bytes as usize, 1, i_addr, x_loc, y_loc, clip)
+    }
+
+    // Draw a SUPER-CHIP 16x16 sprite
+    pub fn display_large_sprite(&mut self, i_addr: usize, x_loc: usize, y_loc: usize, clip: bool) -> Result<bool, FaultKind> {
+        self.display_sprite(16, 2, i_addr, x_loc, y_loc, clip)
     }
 
     pub fn is_key_pressed(&self, key: u8) -> bool{
-        return self.key_state[key as usize];
+        // Only the low nibble selects a key, as on the VIP
+        self.key_state[(key & 0xF) as usize]
     }
 }
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use sdl2::keyboard::Keycode;
+
+use config::Config;
+
+pub const PRESET_NAMES: [&'static str; 2] = ["qwerty", "hex"];
+
+// The COSMAC VIP keypad, row by row
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// Keys the frontend keeps for itself
+const RESERVED: [Keycode; 14] = [
+    Keycode::Escape, Keycode::Backspace,
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5, Keycode::F6,
+    Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10, Keycode::F11, Keycode::F12,
+];
+
+// Which keyboard keys press which of the 16 CHIP-8 keys. Any number of
+// keyboard keys can press the same CHIP-8 key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    keys: HashMap<Keycode, u8>,
+}
+
+impl KeyMap {
+    // The keypad laid over the left of the keyboard, 1234 QWER ASDF ZXCV
+    pub fn qwerty() -> KeyMap {
+        use sdl2::keyboard::Keycode::*;
+        let layout = [
+            Num1, Num2, Num3, Num4,
+            Q, W, E, R,
+            A, S, D, F,
+            Z, X, C, V,
+        ];
+        KeyMap { keys: layout.iter().cloned().zip(KEYPAD.iter().cloned()).collect() }
+    }
+
+    // Each key on the keyboard key of its hex digit, 0-9 and A-F
+    pub fn hex() -> KeyMap {
+        use sdl2::keyboard::Keycode::*;
+        let layout = [
+            Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7,
+            Num8, Num9, A, B, C, D, E, F,
+        ];
+        KeyMap { keys: layout.iter().cloned().zip(0..16).collect() }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyMap> {
+        match name {
+            "qwerty" => Some(KeyMap::qwerty()),
+            "hex" => Some(KeyMap::hex()),
+            _ => None,
+        }
+    }
+
+    // Read the [keys] section: a preset to start from, then any CHIP-8 keys
+    // to remap, each to a comma separated list of SDL key names. A keyboard
+    // key can only be given to one CHIP-8 key.
+    //
+    //     [keys]
+    //     preset = qwerty
+    //     5 = W, Up
+    //     8 = S, Down
+    pub fn from_config(config: &Config) -> Result<KeyMap, String> {
+        let mut keymap = KeyMap::qwerty();
+        if let Some(preset) = config.get("keys", "preset") {
+            keymap = KeyMap::from_name(preset)
+                .ok_or_else(|| format!("unknown key preset {}, expected one of {}", preset, PRESET_NAMES.join(", ")))?;
+        }
+
+        let mut remapped = [false; 16];
+        let mut bound = HashMap::new();
+        for (name, value) in config.section("keys") {
+            if name == "preset" {
+                continue;
+            }
+            let key = match u8::from_str_radix(name, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("{} is not a CHIP-8 key, expected 0-F", name)),
+            };
+            // The first mapping given replaces the preset's
+            if !remapped[key as usize] {
+                keymap.keys.retain(|_, mapped| *mapped != key);
+                remapped[key as usize] = true;
+            }
+            for key_name in value.split(',').map(|key_name| key_name.trim()).filter(|key_name| !key_name.is_empty()) {
+                let keycode = Keycode::from_name(key_name)
+                    .ok_or_else(|| format!("unknown key name {}", key_name))?;
+                if RESERVED.contains(&keycode) {
+                    return Err(format!("{} is reserved for the emulator", key_name));
+                }
+                if let Some(other) = bound.insert(keycode, key) {
+                    if other != key {
+                        return Err(format!("{} is bound to both {:X} and {:X}", key_name, other, key));
+                    }
+                }
+                keymap.keys.insert(keycode, key);
+            }
+        }
+        Ok(keymap)
+    }
+
+    // The CHIP-8 key this keyboard key presses
+    pub fn key(&self, keycode: Keycode) -> Option<u8> {
+        self.keys.get(&keycode).cloned()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::qwerty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::keyboard::Keycode;
+
+    use config::Config;
+    use super::KeyMap;
+
+    fn from_config(text: &str) -> Result<KeyMap, String> {
+        KeyMap::from_config(&Config::parse(text).unwrap())
+    }
+
+    #[test]
+    fn missing_section_is_qwerty() {
+        assert_eq!(from_config("[display]\nscale = 4\n").unwrap(), KeyMap::qwerty());
+        assert_eq!(from_config("[keys]\npreset = hex\n").unwrap(), KeyMap::hex());
+    }
+
+    #[test]
+    fn remapping_replaces_the_preset() {
+        let keymap = from_config("[keys]\n5 = Up, Return\n").unwrap();
+        assert_eq!(keymap.key(Keycode::Up), Some(0x5));
+        assert_eq!(keymap.key(Keycode::Return), Some(0x5));
+        assert_eq!(keymap.key(Keycode::W), None);
+        assert_eq!(keymap.key(Keycode::Q), Some(0x4));
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_eq!(from_config("[keys]\npreset = azerty\n").err().unwrap(),
+                   "unknown key preset azerty, expected one of qwerty, hex");
+        assert_eq!(from_config("[keys]\n5 = Nope\n").err().unwrap(), "unknown key name Nope");
+        assert_eq!(from_config("[keys]\n10 = W\n").err().unwrap(), "10 is not a CHIP-8 key, expected 0-F");
+        assert_eq!(from_config("[keys]\n5 = F1\n").err().unwrap(), "F1 is reserved for the emulator");
+    }
+
+    #[test]
+    fn duplicate_bindings_are_rejected() {
+        assert_eq!(from_config("[keys]\n5 = Up\n8 = Up\n").err().unwrap(), "Up is bound to both 5 and 8");
+        assert!(from_config("[keys]\n5 = Up\n5 = Up, W\n").is_ok());
+    }
+}
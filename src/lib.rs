@@ -0,0 +1,39 @@
+extern crate rand;
+extern crate byteorder;
+
+mod fonts;
+pub mod cpu;
+pub mod instruction;
+pub mod fault;
+pub mod quirks;
+pub mod variant;
+pub mod display;
+pub mod audio;
+pub mod rng;
+pub mod savestate;
+pub mod rewind;
+pub mod movie;
+pub mod interconnect;
+pub mod platform;
+pub mod headless;
+pub mod disasm;
+pub mod debugger;
+pub mod gdbstub;
+pub mod trace;
+pub mod profiler;
+pub mod coverage;
+mod chip8;
+
+pub use cpu::StepOutcome;
+pub use fault::{CpuFault, FaultKind};
+pub use quirks::Quirks;
+pub use variant::Variant;
+pub use display::Display;
+pub use savestate::SaveStateError;
+pub use rng::RngMode;
+pub use instruction::{Instruction, DecodeError, decode};
+pub use debugger::Debugger;
+pub use gdbstub::GdbStub;
+pub use trace::Tracer;
+pub use chip8::{Chip8, RunLimit, RunSummary, FRAMES_PER_SECOND, DEFAULT_INSTRUCTIONS_PER_FRAME};
+pub use interconnect::RomTooLarge;
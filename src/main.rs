@@ -1,27 +1,312 @@
-extern crate rand;
-extern crate byteorder;
-extern crate time;
+extern crate rust_chip8;
 extern crate sdl2;
 
 use std::env;
 use std::fs;
-use std::io::Read;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
+use std::process;
 
-use cpu::CPU;
-use interconnect::Interconnect;
+use rust_chip8::{Chip8, Debugger, GdbStub, Tracer, RunLimit};
+use rust_chip8::headless::HeadlessPlatform;
+use rust_chip8::movie::{Movie, MovieRecorder, MoviePlayer};
+use rust_chip8::rng;
+use config::Config;
+use controller::ControllerMap;
+use display_settings::DisplaySettings;
+use keymap::KeyMap;
+use options::{Options, USAGE};
+use sdl_frontend::SdlPlatform;
 
-mod fonts;
-mod cpu;
-mod interconnect;
+mod config;
+mod controller;
+mod display_settings;
+mod keymap;
+mod options;
+mod sdl_frontend;
+
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+const DEFAULT_CONFIG_PATH: &'static str = "chip8.ini";
 
 fn main() {
-    let program_file_name = env::args().nth(1).unwrap();
-    let program = read_bin(program_file_name);
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let config = read_config(&options);
+    let keymap = match KeyMap::from_config(&config) {
+        Ok(keymap) => keymap,
+        Err(err) => {
+            eprintln!("Bad key mapping: {}", err);
+            process::exit(2);
+        }
+    };
+    let rom_name = Path::new(&options.rom_path).file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let controller_map = match ControllerMap::from_config(&config, &rom_name) {
+        Ok(controller_map) => controller_map,
+        Err(err) => {
+            eprintln!("Bad controller mapping: {}", err);
+            process::exit(2);
+        }
+    };
+    let mut display = match DisplaySettings::from_config(&config) {
+        Ok(display) => display,
+        Err(err) => {
+            eprintln!("Bad display settings: {}", err);
+            process::exit(2);
+        }
+    };
+    if let Some(scale) = options.scale {
+        display.scale = scale;
+    }
+    if let Some(palette) = options.palette {
+        display.palette = palette;
+    }
+    display.fullscreen |= options.fullscreen;
+    if let Some(persistence) = options.persistence {
+        display.persistence = persistence;
+    }
+    if let Some(decay) = options.decay {
+        display.decay = decay;
+    }
+
+    let program = read_bin(&options.rom_path);
+    let mut chip8 = Chip8::new(Vec::new());
+    chip8.set_save_path(&options.rom_path);
+    if let Some(ipf) = options.instructions_per_frame {
+        chip8.set_instructions_per_frame(ipf);
+    }
+    let variant = options.variant.unwrap_or_default();
+    chip8.set_variant(variant);
+    chip8.set_quirks(options.quirks.unwrap_or(variant.default_quirks()));
+    if let Err(err) = chip8.load_rom(&program) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    if let Some(mode) = options.rng_mode {
+        chip8.set_rng_mode(mode);
+    }
+    if let Some(seed) = options.seed {
+        chip8.set_rng_seed(seed);
+    }
+
+    if let Some(ref path) = options.trace {
+        let file = match fs::File::create(path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Could not create trace file {}: {}", path, err);
+                process::exit(1);
+            }
+        };
+        let mut tracer = Tracer::new(BufWriter::new(file));
+        for &(start, end) in &options.trace_ranges {
+            tracer.add_range(start, end);
+        }
+        chip8.start_trace(tracer);
+    }
+
+    if options.profile.is_some() || options.profile_folded.is_some() {
+        chip8.start_profiling();
+    }
+    if options.coverage.is_some() {
+        chip8.start_coverage();
+    }
+
+    let movie = options.play.as_ref().map(|path| read_movie(path, &program, &mut chip8));
+
+    // False if a movie's playback diverged from the recording
+    let matched = if options.headless {
+        run_headless(&mut chip8, &options, movie)
+    } else if let Some(ref path) = options.record {
+        let seed = options.seed.unwrap_or_else(rng::random_seed);
+        chip8.set_rng_seed(seed);
+        let mut recorder = MovieRecorder::new(SdlPlatform::new(keymap, controller_map, display), Movie::new(&program, &chip8, seed));
+        chip8.run(&mut recorder);
+        let (_, movie) = recorder.finish();
+        let mut writer = BufWriter::new(fs::File::create(path).unwrap());
+        movie.write(&mut writer).unwrap();
+        writer.flush().unwrap();
+        println!("Recorded {} frames to {}", movie.frames, path);
+        true
+    } else if let Some(movie) = movie {
+        let mut player = MoviePlayer::new(SdlPlatform::new(keymap, controller_map, display), movie);
+        chip8.run(&mut player);
+        report_playback(&player)
+    } else {
+        let mut platform = SdlPlatform::new(keymap, controller_map, display);
+        chip8.set_rewind_frames(options.rewind_frames);
+        if options.debug {
+            chip8.attach_debugger(Debugger::from_stdin());
+        }
+        if let Some(port) = options.gdb_port {
+            match GdbStub::bind(port) {
+                Ok(gdb) => {
+                    println!("Waiting for a GDB connection on localhost:{}", port);
+                    chip8.attach_gdb(gdb);
+                },
+                Err(err) => {
+                    eprintln!("Could not listen on port {}: {}", port, err);
+                    process::exit(1);
+                }
+            }
+        }
+        chip8.run(&mut platform);
+        true
+    };
+
+    if let Some(err) = chip8.take_trace_error() {
+        eprintln!("Stopped tracing: {}", err);
+    }
+    write_profile(&chip8, &options);
+    write_coverage(&chip8, &options);
+    if !matched {
+        process::exit(1);
+    }
+    if let Some(fault) = chip8.fault() {
+        eprintln!("CPU fault: {}", fault);
+        process::exit(1);
+    }
+}
+
+// The --config file, or chip8.ini if there is one, or no settings at all
+fn read_config(options: &Options) -> Config {
+    let path = match options.config {
+        Some(ref path) => path.as_str(),
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH,
+        None => return Config::new(),
+    };
+    match Config::load(path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Could not read settings: {}", err);
+            process::exit(2);
+        }
+    }
+}
+
+// Returns false if a movie was played back and diverged from the recording
+fn run_headless(chip8: &mut Chip8, options: &Options, movie: Option<Movie>) -> bool {
+    let mut player = movie.map(|movie| MoviePlayer::new(HeadlessPlatform::new(), movie));
+    let summary = match player {
+        Some(ref mut player) => {
+            let limit = options.limit.unwrap_or(RunLimit::Frames(player.frames() + 1));
+            chip8.run_for(player, limit)
+        },
+        None => {
+            let limit = options.limit.unwrap_or(RunLimit::Frames(DEFAULT_HEADLESS_FRAMES));
+            chip8.run_headless(limit)
+        },
+    };
+
+    println!("Ran {} instructions over {} frames{}",
+             summary.instructions,
+             summary.frames,
+             if summary.halted { " (halted)" } else { "" });
+    print_state(chip8);
 
-    let interconnect = Interconnect::new(program);
-    let mut cpu: CPU = CPU::new(interconnect);
-    cpu.start();
+    if let Some(ref path) = options.dump_ram {
+        let mut file = fs::File::create(path).unwrap();
+        file.write_all(chip8.ram()).unwrap();
+    }
+
+    player.as_ref().is_none_or(report_playback)
+}
+
+fn write_profile(chip8: &Chip8, options: &Options) {
+    let profiler = match chip8.profiler() {
+        Some(profiler) => profiler,
+        None => return,
+    };
+    if let Some(ref path) = options.profile {
+        let result = fs::File::create(path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            profiler.write_report(&mut writer, chip8.ram())?;
+            writer.flush()
+        });
+        match result {
+            Ok(()) => println!("Wrote profile of {} instructions to {}", profiler.instructions(), path),
+            Err(err) => eprintln!("Could not write profile to {}: {}", path, err),
+        }
+    }
+    if let Some(ref path) = options.profile_folded {
+        let result = fs::File::create(path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            profiler.write_folded(&mut writer)?;
+            writer.flush()
+        });
+        if let Err(err) = result {
+            eprintln!("Could not write folded stacks to {}: {}", path, err);
+        }
+    }
+}
+
+fn write_coverage(chip8: &Chip8, options: &Options) {
+    let (coverage, path) = match (chip8.coverage(), options.coverage.as_ref()) {
+        (Some(coverage), Some(path)) => (coverage, path),
+        _ => return,
+    };
+    let result = fs::File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        coverage.write_annotated(&mut writer, chip8.ram())?;
+        writer.flush()
+    });
+    match result {
+        Ok(()) => {
+            let (executed, read, untouched) = coverage.summary();
+            println!("Coverage: {} bytes executed, {} read as data, {} never touched, written to {}",
+                     executed, read, untouched, path);
+        },
+        Err(err) => eprintln!("Could not write coverage to {}: {}", path, err),
+    }
+}
+
+fn print_state(chip8: &Chip8) {
+    let regs: Vec<String> = chip8.registers().iter().map(|r| format!("{:02X}", r)).collect();
+    println!("V0-VF: {}", regs.join(" "));
+    println!("I: {:04X}  PC: {:04X}  SP: {}  DT: {}  ST: {}",
+             chip8.reg_i(), chip8.reg_pc(), chip8.reg_sp(), chip8.reg_dt(), chip8.reg_st());
+
+    let display_state = chip8.display_state();
+    for y in 0..display_state.height() {
+        let row: String = (0..display_state.width())
+            .map(|x| if display_state.pixel(x, y) { '#' } else { '.' })
+            .collect();
+        println!("{}", row);
+    }
+}
+
+// Read a movie and set chip8 up to play it back, exiting if that's not possible
+fn read_movie(path: &str, program: &[u8], chip8: &mut Chip8) -> Movie {
+    let result = fs::File::open(path)
+        .map_err(|err| err.into())
+        .and_then(|file| Movie::read(&mut BufReader::new(file)))
+        .and_then(|movie| movie.configure(program, chip8).map(|_| movie));
+    match result {
+        Ok(movie) => movie,
+        Err(err) => {
+            eprintln!("Could not play {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
+
+// Returns false if playback diverged from the recording
+fn report_playback<P>(player: &MoviePlayer<P>) -> bool {
+    match player.desync() {
+        Some(frame) => {
+            eprintln!("Playback diverged from the recording at frame {}", frame);
+            return false;
+        },
+        None if player.is_finished() => println!("Playback matched the recording"),
+        None => println!("Playback stopped before the end of the recording"),
+    }
+    true
 }
 
 fn read_bin<P: AsRef<Path>>(path: P) -> Vec<u8> {
@@ -30,4 +315,3 @@ fn read_bin<P: AsRef<Path>>(path: P) -> Vec<u8> {
     file.read_to_end(&mut file_buf).unwrap();
     file_buf
 }
-
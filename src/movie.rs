@@ -0,0 +1,430 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use chip8::Chip8;
+use interconnect::RomTooLarge;
+use display::Display;
+use platform::{VideoSink, Beeper, InputSource, HostCommand};
+use audio::AudioPattern;
+use quirks::Quirks;
+use savestate::SaveStateError;
+use variant::Variant;
+use rng::RngMode;
+
+// Movie files start with MAGIC followed by VERSION as a big endian u16
+pub const MAGIC: &'static [u8; 4] = b"C8MV";
+
+// Bump whenever the layout written by Movie::write changes
+pub const VERSION: u16 = 3;
+
+// Everything needed to replay a session exactly: the settings the machine
+// ran with and the keys held during every frame. A checksum of the display
+// after each frame lets playback notice if it no longer matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32,
+    pub rng_mode: RngMode,
+    pub rng_seed: u64,
+
+    // Number of frames recorded
+    pub frames: u64,
+
+    // The keys held from the given frame on, bit n for key n
+    pub key_changes: Vec<(u64, u16)>,
+
+    // display_checksum of the display at the end of each frame
+    pub display_checksums: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+
+    // Not a movie file at all
+    BadMagic,
+
+    // Written by a different version of the emulator
+    UnsupportedVersion(u16),
+
+    // Recorded with a different ROM than the one loaded
+    RomMismatch,
+
+    // The ROM doesn't fit in memory for the recorded variant
+    RomTooLarge(RomTooLarge),
+
+    // A field holds a value that can't be played back
+    Invalid(&'static str),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::Io(ref err) => write!(f, "{}", err),
+            MovieError::BadMagic => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(version) =>
+                write!(f, "movie version {} is not supported, expected {}", version, VERSION),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different rom"),
+            MovieError::RomTooLarge(ref err) => write!(f, "{}", err),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {}", field),
+        }
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> MovieError {
+        MovieError::Io(err)
+    }
+}
+
+impl From<RomTooLarge> for MovieError {
+    fn from(err: RomTooLarge) -> MovieError {
+        MovieError::RomTooLarge(err)
+    }
+}
+
+impl Movie {
+    // Start an empty movie of a session about to run rom on chip8, whose RNG
+    // must have been seeded with rng_seed
+    pub fn new(rom: &[u8], chip8: &Chip8, rng_seed: u64) -> Movie {
+        Movie {
+            rom_hash: fnv1a(rom),
+            variant: chip8.variant(),
+            quirks: chip8.quirks(),
+            instructions_per_frame: chip8.instructions_per_frame(),
+            rng_mode: chip8.rng_mode(),
+            rng_seed: rng_seed,
+            frames: 0,
+            key_changes: Vec::new(),
+            display_checksums: Vec::new(),
+        }
+    }
+
+    // Set chip8 up the way it was when the movie was recorded
+    pub fn configure(&self, rom: &[u8], chip8: &mut Chip8) -> Result<(), MovieError> {
+        if fnv1a(rom) != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        // The variant decides how much memory there is for the rom
+        chip8.set_variant(self.variant);
+        chip8.load_rom(rom)?;
+        chip8.set_quirks(self.quirks);
+        chip8.set_instructions_per_frame(self.instructions_per_frame);
+        chip8.set_rng_mode(self.rng_mode);
+        chip8.set_rng_seed(self.rng_seed);
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u16::<BigEndian>(VERSION)?;
+        writer.write_u64::<BigEndian>(self.rom_hash)?;
+        writer.write_u8(self.variant as u8)?;
+        self.quirks.write_state(writer)?;
+        writer.write_u32::<BigEndian>(self.instructions_per_frame)?;
+        writer.write_u8(self.rng_mode as u8)?;
+        writer.write_u64::<BigEndian>(self.rng_seed)?;
+        writer.write_u64::<BigEndian>(self.frames)?;
+
+        writer.write_u32::<BigEndian>(self.key_changes.len() as u32)?;
+        for &(frame, keys) in self.key_changes.iter() {
+            writer.write_u64::<BigEndian>(frame)?;
+            writer.write_u16::<BigEndian>(keys)?;
+        }
+        for checksum in self.display_checksums.iter() {
+            writer.write_u64::<BigEndian>(*checksum)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Movie, MovieError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = reader.read_u16::<BigEndian>()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = reader.read_u64::<BigEndian>()?;
+        let variant = match Variant::from_index(reader.read_u8()?) {
+            Some(variant) => variant,
+            None => return Err(MovieError::Invalid("variant")),
+        };
+        let quirks = Quirks::read_state(reader)?;
+        let instructions_per_frame = reader.read_u32::<BigEndian>()?;
+        let rng_mode = match RngMode::from_index(reader.read_u8()?) {
+            Some(mode) => mode,
+            None => return Err(MovieError::Invalid("rng mode")),
+        };
+        let rng_seed = reader.read_u64::<BigEndian>()?;
+        let frames = reader.read_u64::<BigEndian>()?;
+
+        let num_changes = reader.read_u32::<BigEndian>()?;
+        let mut key_changes = Vec::new();
+        for _ in 0..num_changes {
+            let frame = reader.read_u64::<BigEndian>()?;
+            let keys = reader.read_u16::<BigEndian>()?;
+            if frame >= frames || key_changes.last().is_some_and(|&(last, _)| frame <= last) {
+                return Err(MovieError::Invalid("key change"));
+            }
+            key_changes.push((frame, keys));
+        }
+        let mut display_checksums = Vec::new();
+        for _ in 0..frames {
+            display_checksums.push(reader.read_u64::<BigEndian>()?);
+        }
+
+        Ok(Movie {
+            rom_hash: rom_hash,
+            variant: variant,
+            quirks: quirks,
+            instructions_per_frame: instructions_per_frame,
+            rng_mode: rng_mode,
+            rng_seed: rng_seed,
+            frames: frames,
+            key_changes: key_changes,
+            display_checksums: display_checksums,
+        })
+    }
+}
+
+// Wraps the platform a session runs on, noting the keys at the start of
+// every frame and the display at the end of it
+pub struct MovieRecorder<P> {
+    inner: P,
+    movie: Movie,
+    keys: u16,
+    display_checksum: u64,
+}
+
+impl<P> MovieRecorder<P> {
+    pub fn new(inner: P, movie: Movie) -> MovieRecorder<P> {
+        MovieRecorder {
+            inner: inner,
+            movie: movie,
+            keys: 0,
+            display_checksum: display_checksum(&Display::new()),
+        }
+    }
+
+    // Stop recording, handing back the platform and the finished movie
+    pub fn finish(mut self) -> (P, Movie) {
+        self.end_frame();
+        (self.inner, self.movie)
+    }
+
+    // Note the display left by the last frame recorded, if not done already
+    fn end_frame(&mut self) {
+        if (self.movie.display_checksums.len() as u64) < self.movie.frames {
+            self.movie.display_checksums.push(self.display_checksum);
+        }
+    }
+}
+
+// Plays a movie's keys back in place of the wrapped platform's, which is
+// still used for output and to quit
+pub struct MoviePlayer<P> {
+    inner: P,
+    movie: Movie,
+    frame: u64,
+    next_change: usize,
+    keys: u16,
+    display_checksum: u64,
+    desync: Option<u64>,
+}
+
+impl<P> MoviePlayer<P> {
+    pub fn new(inner: P, movie: Movie) -> MoviePlayer<P> {
+        MoviePlayer {
+            inner: inner,
+            movie: movie,
+            frame: 0,
+            next_change: 0,
+            keys: 0,
+            display_checksum: display_checksum(&Display::new()),
+            desync: None,
+        }
+    }
+
+    // Number of frames in the movie
+    pub fn frames(&self) -> u64 {
+        self.movie.frames
+    }
+
+    // True once every recorded frame has been played
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    // The first frame whose display differed from the recording
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    // Compare the display from the frame that just ended with the recording
+    fn check_frame(&mut self) {
+        if self.frame == 0 || self.desync.is_some() {
+            return;
+        }
+        let frame = self.frame - 1;
+        if self.movie.display_checksums.get(frame as usize) != Some(&self.display_checksum) {
+            self.desync = Some(frame);
+        }
+    }
+}
+
+impl<P: InputSource> InputSource for MovieRecorder<P> {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        let quit = self.inner.poll(key_state);
+
+        self.end_frame();
+        if quit {
+            // Nothing runs in this frame, so it isn't part of the recording
+            return true;
+        }
+        let keys = keys_to_mask(key_state);
+        if keys != self.keys {
+            self.movie.key_changes.push((self.movie.frames, keys));
+            self.keys = keys;
+        }
+        self.movie.frames += 1;
+        false
+    }
+
+    // Loading a state or rewinding would make the recording impossible to replay
+    fn take_commands(&mut self) -> Vec<HostCommand> {
+        self.inner.take_commands().into_iter()
+            .filter(|command| matches!(*command, HostCommand::SaveState(_)))
+            .collect()
+    }
+
+    fn command_finished(&mut self, command: HostCommand, result: Result<Option<PathBuf>, SaveStateError>) {
+        self.inner.command_finished(command, result);
+    }
+}
+
+impl<P: InputSource> InputSource for MoviePlayer<P> {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        let mut host_keys = [false; 16];
+        let quit = self.inner.poll(&mut host_keys);
+
+        self.check_frame();
+        if self.is_finished() {
+            return true;
+        }
+        if let Some(&(frame, keys)) = self.movie.key_changes.get(self.next_change) {
+            if frame == self.frame {
+                self.keys = keys;
+                self.next_change += 1;
+            }
+        }
+        *key_state = mask_to_keys(self.keys);
+        self.frame += 1;
+        quit
+    }
+
+    fn take_commands(&mut self) -> Vec<HostCommand> {
+        self.inner.take_commands().into_iter()
+            .filter(|command| matches!(*command, HostCommand::SaveState(_)))
+            .collect()
+    }
+
+    fn command_finished(&mut self, command: HostCommand, result: Result<Option<PathBuf>, SaveStateError>) {
+        self.inner.command_finished(command, result);
+    }
+}
+
+impl<P: VideoSink> VideoSink for MovieRecorder<P> {
+    fn present(&mut self, display_state: &Display) {
+        self.display_checksum = display_checksum(display_state);
+        self.inner.present(display_state);
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+}
+
+impl<P: VideoSink> VideoSink for MoviePlayer<P> {
+    fn present(&mut self, display_state: &Display) {
+        self.display_checksum = display_checksum(display_state);
+        self.inner.present(display_state);
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+}
+
+impl<P: Beeper> Beeper for MovieRecorder<P> {
+    fn start_beep(&mut self) {
+        self.inner.start_beep();
+    }
+
+    fn stop_beep(&mut self) {
+        self.inner.stop_beep();
+    }
+
+    fn set_pattern(&mut self, pattern: &AudioPattern) {
+        self.inner.set_pattern(pattern);
+    }
+}
+
+impl<P: Beeper> Beeper for MoviePlayer<P> {
+    fn start_beep(&mut self) {
+        self.inner.start_beep();
+    }
+
+    fn stop_beep(&mut self) {
+        self.inner.stop_beep();
+    }
+
+    fn set_pattern(&mut self, pattern: &AudioPattern) {
+        self.inner.set_pattern(pattern);
+    }
+}
+
+// 64 bit FNV-1a, used to recognise ROMs and displays
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+pub fn display_checksum(display_state: &Display) -> u64 {
+    let mut bytes = Vec::new();
+    display_state.write_state(&mut bytes).unwrap();
+    fnv1a(&bytes)
+}
+
+fn keys_to_mask(key_state: &[bool; 16]) -> u16 {
+    key_state.iter().enumerate()
+        .filter(|&(_, pressed)| *pressed)
+        .fold(0, |mask, (key, _)| mask | 1 << key)
+}
+
+fn mask_to_keys(mask: u16) -> [bool; 16] {
+    let mut key_state = [false; 16];
+    for (key, pressed) in key_state.iter_mut().enumerate() {
+        *pressed = mask & (1 << key) != 0;
+    }
+    key_state
+}
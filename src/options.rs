@@ -0,0 +1,304 @@
+use rust_chip8::{RunLimit, Quirks, Variant, RngMode, FRAMES_PER_SECOND};
+use rust_chip8::rng::RNG_MODE_NAMES;
+use rust_chip8::quirks::PRESET_NAMES;
+use rust_chip8::variant::VARIANT_NAMES;
+use display_settings::{self, Palette, Persistence};
+
+const DEFAULT_REWIND_SECONDS: u64 = 10;
+
+pub const USAGE: &'static str = "Usage: rust_chip8 [options] <rom>
+
+Options:
+    --ipf <n>               Instructions executed per 60 Hz frame (default 11)
+    --hz <n>                CPU speed in instructions per second, instead of --ipf
+    --variant <name>        Instruction set: chip8 (default), schip, xochip
+    --quirks <preset>       Interpreter behaviour: legacy, vip, chip48, schip, xochip
+                            (default depends on --variant)
+    --seed <n>              Seed for Cxkk, the same seed gives the same numbers
+    --rng <mode>            Random numbers from xorshift (default) or counter
+    --scale <n>             Window pixels per CHIP-8 pixel (default 10)
+    --palette <colours>     mono (default), amber, green, lcd, octo, or hex
+                            colours: background,foreground[,plane 2,both planes]
+    --fullscreen            Start fullscreen
+    --persistence <mode>    Keep pixels visible after they go out to hide flicker:
+                            off (default), fade them out, or blend each frame
+                            with the one before
+    --decay <fraction>      Fraction of the glow removed each frame when fading
+                            (default 0.35)
+    --config <file>         Settings file (default chip8.ini if it exists)
+    --rewind <seconds>      How far back rewind can go (default 10, 0 turns it off)
+    --record <file>         Record the keys pressed to a movie file
+    --play <file>           Play a movie back instead of reading the keyboard
+    --debug                 Start paused in the debugger, which reads commands
+                            from the terminal
+    --trace <file>          Log every instruction executed to a file
+    --trace-range <start>-<end>
+                            Only log instructions in this hex address range,
+                            can be given more than once
+    --profile <file>        Write a report of where instructions were executed at exit
+    --profile-folded <file> Write the call stacks executed at exit, for flame graphs
+    --coverage <file>       Write an annotated disassembly of the bytes of the rom
+                            executed, read as data or never touched at exit
+    --gdb <port>            Wait for a GDB remote protocol client on a localhost port
+    --headless              Run without a window or audio device
+    --frames <n>            Stop a headless run after n frames (default 600)
+    --instructions <n>      Stop a headless run after n instructions
+    --dump-ram <file>       Write RAM to a file when a headless run ends
+
+Keys:
+    1 2 3 4                 The CHIP-8 keypad  1 2 3 C
+    Q W E R                                    4 5 6 D
+    A S D F                                    7 8 9 E
+    Z X C V                                    A 0 B F
+                            unless the [keys] section of the settings file
+                            maps them differently
+    Controller              D-pad and left stick press 2 4 6 8, A B X Y press
+                            5 0 1 3, unless the [controller] section or the
+                            rom's [controller.<name>] section maps them
+    Backspace               Hold to rewind
+    F11                     Toggle fullscreen
+    F12                     Break into the debugger
+    F1-F9                   Load save state slot 1-9
+    Shift+F1-F9             Save to slot 1-9, stored next to the rom";
+
+pub struct Options {
+    pub rom_path: String,
+    pub headless: bool,
+    pub limit: Option<RunLimit>,
+    pub dump_ram: Option<String>,
+    pub instructions_per_frame: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub variant: Option<Variant>,
+    pub seed: Option<u64>,
+    pub rng_mode: Option<RngMode>,
+    pub record: Option<String>,
+    pub play: Option<String>,
+
+    // How many frames rewind keeps, from --rewind in seconds
+    pub rewind_frames: usize,
+    pub debug: bool,
+    pub gdb_port: Option<u16>,
+    pub trace: Option<String>,
+    pub trace_ranges: Vec<(u16, u16)>,
+    pub profile: Option<String>,
+    pub profile_folded: Option<String>,
+    pub coverage: Option<String>,
+    pub config: Option<String>,
+    pub scale: Option<u32>,
+    pub palette: Option<Palette>,
+    pub fullscreen: bool,
+    pub persistence: Option<Persistence>,
+    pub decay: Option<f32>,
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut rom_path = None;
+        let mut headless = false;
+        let mut limit = None;
+        let mut dump_ram = None;
+        let mut instructions_per_frame = None;
+        let mut quirks = None;
+        let mut variant = None;
+        let mut rewind_frames = DEFAULT_REWIND_SECONDS as usize * FRAMES_PER_SECOND as usize;
+        let mut record = None;
+        let mut seed = None;
+        let mut rng_mode = None;
+        let mut play = None;
+        let mut debug = false;
+        let mut gdb_port = None;
+        let mut trace = None;
+        let mut trace_ranges = Vec::new();
+        let mut profile = None;
+        let mut profile_folded = None;
+        let mut coverage = None;
+        let mut config = None;
+        let mut scale = None;
+        let mut palette = None;
+        let mut fullscreen = false;
+        let mut persistence = None;
+        let mut decay = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--headless" => headless = true,
+                "--debug" => debug = true,
+                "--trace" => trace = Some(expect_value(&arg, args.next())?),
+                "--trace-range" => trace_ranges.push(parse_range(&arg, args.next())?),
+                "--profile" => profile = Some(expect_value(&arg, args.next())?),
+                "--profile-folded" => profile_folded = Some(expect_value(&arg, args.next())?),
+                "--coverage" => coverage = Some(expect_value(&arg, args.next())?),
+                "--config" => config = Some(expect_value(&arg, args.next())?),
+                "--scale" => scale = Some(display_settings::parse_scale(&expect_value(&arg, args.next())?)?),
+                "--palette" => palette = Some(Palette::parse(&expect_value(&arg, args.next())?)?),
+                "--fullscreen" => fullscreen = true,
+                "--persistence" => persistence = Some(Persistence::parse(&expect_value(&arg, args.next())?)?),
+                "--decay" => decay = Some(display_settings::parse_decay(&expect_value(&arg, args.next())?)?),
+                "--gdb" => {
+                    let port = parse_number(&arg, args.next())?;
+                    if port > u16::MAX as u64 {
+                        return Err(format!("{} is not a valid port", port));
+                    }
+                    gdb_port = Some(port as u16);
+                },
+                "--frames" | "--instructions" => {
+                    if limit.is_some() {
+                        return Err("only one of --frames and --instructions may be given".to_string());
+                    }
+                    let n = parse_number(&arg, args.next())?;
+                    limit = Some(if arg == "--frames" {
+                        RunLimit::Frames(n)
+                    } else {
+                        RunLimit::Instructions(n)
+                    });
+                },
+                "--rewind" => {
+                    let seconds = parse_number(&arg, args.next())?;
+                    rewind_frames = match seconds.checked_mul(FRAMES_PER_SECOND as u64) {
+                        Some(frames) if frames <= usize::MAX as u64 => frames as usize,
+                        _ => return Err(format!("--rewind {} is too long", seconds)),
+                    };
+                },
+                "--seed" => seed = Some(parse_number(&arg, args.next())?),
+                "--rng" => {
+                    let name = expect_value(&arg, args.next())?;
+                    match RngMode::from_name(&name) {
+                        Some(mode) => rng_mode = Some(mode),
+                        None => return Err(format!("unknown rng mode {}, expected one of {}",
+                                                   name, RNG_MODE_NAMES.join(", "))),
+                    }
+                },
+                "--record" => record = Some(expect_value(&arg, args.next())?),
+                "--play" => play = Some(expect_value(&arg, args.next())?),
+                "--dump-ram" => dump_ram = Some(expect_value(&arg, args.next())?),
+                "--ipf" | "--hz" => {
+                    if instructions_per_frame.is_some() {
+                        return Err("only one of --ipf and --hz may be given".to_string());
+                    }
+                    let n = parse_number(&arg, args.next())?;
+                    let ipf = if arg == "--hz" {
+                        (n + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64
+                    } else {
+                        n
+                    };
+                    if ipf == 0 {
+                        return Err(format!("{} is too slow to execute any instructions", arg));
+                    }
+                    instructions_per_frame = Some(ipf as u32);
+                },
+                "--quirks" => {
+                    let name = expect_value(&arg, args.next())?;
+                    match Quirks::from_name(&name) {
+                        Some(preset) => quirks = Some(preset),
+                        None => return Err(format!("unknown quirks preset {}, expected one of {}",
+                                                   name, PRESET_NAMES.join(", "))),
+                    }
+                },
+                "--variant" => {
+                    let name = expect_value(&arg, args.next())?;
+                    match Variant::from_name(&name) {
+                        Some(v) => variant = Some(v),
+                        None => return Err(format!("unknown variant {}, expected one of {}",
+                                                   name, VARIANT_NAMES.join(", "))),
+                    }
+                },
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => rom_path = Some(arg),
+            }
+        }
+
+        if record.is_some() && play.is_some() {
+            return Err("only one of --record and --play may be given".to_string());
+        }
+        if record.is_some() && headless {
+            return Err("--record needs a window to take input from".to_string());
+        }
+
+        if (debug || gdb_port.is_some()) && (headless || record.is_some() || play.is_some()) {
+            return Err("--debug and --gdb can't be combined with --headless, --record or --play".to_string());
+        }
+
+        if !trace_ranges.is_empty() && trace.is_none() {
+            return Err("--trace-range needs --trace".to_string());
+        }
+
+        let rom_path = match rom_path {
+            Some(rom_path) => rom_path,
+            None => return Err("no rom given".to_string()),
+        };
+
+        Ok(Options {
+            rom_path: rom_path,
+            headless: headless,
+            limit: limit,
+            dump_ram: dump_ram,
+            instructions_per_frame: instructions_per_frame,
+            quirks: quirks,
+            variant: variant,
+            rewind_frames: rewind_frames,
+            seed: seed,
+            rng_mode: rng_mode,
+            record: record,
+            play: play,
+            debug: debug,
+            gdb_port: gdb_port,
+            trace: trace,
+            trace_ranges: trace_ranges,
+            profile: profile,
+            profile_folded: profile_folded,
+            coverage: coverage,
+            config: config,
+            scale: scale,
+            palette: palette,
+            fullscreen: fullscreen,
+            persistence: persistence,
+            decay: decay,
+        })
+    }
+}
+
+fn expect_value(option: &str, value: Option<String>) -> Result<String, String> {
+    match value {
+        Some(value) => Ok(value),
+        None => Err(format!("{} expects a value", option)),
+    }
+}
+
+fn parse_number(option: &str, value: Option<String>) -> Result<u64, String> {
+    let value = expect_value(option, value)?;
+    value.parse().map_err(|_| format!("{} expects a number, got {}", option, value))
+}
+
+// start-end in hex, both ends included
+fn parse_range(option: &str, value: Option<String>) -> Result<(u16, u16), String> {
+    let value = expect_value(option, value)?;
+    let bounds: Vec<Option<u16>> = value.splitn(2, '-')
+        .map(|bound| u16::from_str_radix(bound.trim_start_matches("0x"), 16).ok())
+        .collect();
+    match bounds[..] {
+        [Some(start), Some(end)] if start <= end => Ok((start, end)),
+        _ => Err(format!("{} expects a hex address range like 200-2FF, got {}", option, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rewind_seconds_become_frames() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().rewind_frames, 600);
+        assert_eq!(parse(&["--rewind", "2", "rom.ch8"]).unwrap().rewind_frames, 120);
+        assert_eq!(parse(&["--rewind", "0", "rom.ch8"]).unwrap().rewind_frames, 0);
+    }
+
+    #[test]
+    fn rewind_too_long_is_rejected() {
+        let err = parse(&["--rewind", "18446744073709551615", "rom.ch8"]).err().unwrap();
+        assert_eq!(err, "--rewind 18446744073709551615 is too long");
+    }
+}
@@ -0,0 +1,59 @@
+// The emulator core never talks to a window, audio device or keyboard
+// directly. Frontends implement these traits over whatever the host provides.
+
+use std::path::PathBuf;
+
+use display::Display;
+use audio::AudioPattern;
+use savestate::SaveStateError;
+
+pub trait VideoSink {
+    // Show the current framebuffer
+    fn present(&mut self, display_state: &Display);
+
+    // Called at the end of every frame, after present if the framebuffer
+    // changed, for sinks that animate between changes
+    fn refresh(&mut self) {}
+}
+
+pub trait Beeper {
+    fn start_beep(&mut self);
+
+    fn stop_beep(&mut self);
+
+    // Play this pattern at its pitch from now on whenever beeping
+    fn set_pattern(&mut self, pattern: &AudioPattern);
+}
+
+// Requests from the user that the emulator carries out rather than the program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostCommand {
+    // Save or load the numbered save state slot
+    SaveState(u8),
+    LoadState(u8),
+
+    // Run backwards while true, sent when the rewind key goes down and up
+    Rewind(bool),
+
+    // Stop and hand control to the debugger, attaching one if needed
+    Break,
+}
+
+pub trait InputSource {
+    // Update key_state from pending host events, returns true if the user asked to quit
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool;
+
+    // Commands issued since the last call, collected while polling
+    fn take_commands(&mut self) -> Vec<HostCommand> {
+        Vec::new()
+    }
+
+    // How a command from take_commands turned out, with the save state file
+    // it used if any
+    fn command_finished(&mut self, _command: HostCommand, _result: Result<Option<PathBuf>, SaveStateError>) {}
+}
+
+// Everything the CPU needs from the host in one bound
+pub trait Platform: VideoSink + Beeper + InputSource {}
+
+impl<T: VideoSink + Beeper + InputSource> Platform for T {}
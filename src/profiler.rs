@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+use disasm::DisasmLine;
+use instruction::Instruction;
+
+// A level of the call stack, named after where the subroutine starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Frame {
+    // The program itself, outside any subroutine
+    Main,
+
+    Subroutine(u16),
+
+    // A subroutine whose CALL wasn't seen, e.g. when profiling started or a
+    // save state was loaded inside it
+    Unknown,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Frame::Main => write!(f, "main"),
+            Frame::Subroutine(addr) => write!(f, "sub_{:04X}", addr),
+            Frame::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SubroutineStats {
+    pub calls: u64,
+
+    // Instructions executed in the subroutine itself
+    pub self_instructions: u64,
+
+    // Instructions executed in it and everything it called
+    pub total_instructions: u64,
+}
+
+// Counts where instructions are executed, kept by the CPU while profiling
+// is enabled
+pub struct Profiler {
+    instructions: u64,
+
+    by_addr: HashMap<u16, u64>,
+    by_pattern: HashMap<&'static str, u64>,
+    subroutines: HashMap<Frame, SubroutineStats>,
+
+    // The call stack as profiled, Main at the bottom
+    frames: Vec<Frame>,
+
+    // Instructions executed with each distinct call stack
+    stacks: HashMap<Vec<Frame>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            instructions: 0,
+            by_addr: HashMap::new(),
+            by_pattern: HashMap::new(),
+            subroutines: HashMap::new(),
+            frames: vec![Frame::Main],
+            stacks: HashMap::new(),
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Executions of the instruction at each address
+    pub fn by_addr(&self) -> &HashMap<u16, u64> {
+        &self.by_addr
+    }
+
+    // Executions of each kind of instruction, keyed by Instruction::pattern
+    pub fn by_pattern(&self) -> &HashMap<&'static str, u64> {
+        &self.by_pattern
+    }
+
+    pub fn subroutines(&self) -> &HashMap<Frame, SubroutineStats> {
+        &self.subroutines
+    }
+
+    // Count an instruction the CPU executed from pc. sp is the stack pointer
+    // it left behind, which the profiled call stack is kept in step with.
+    pub fn record(&mut self, pc: u16, instruction: Instruction, sp: u8) {
+        self.instructions += 1;
+        *self.by_addr.entry(pc).or_insert(0) += 1;
+        *self.by_pattern.entry(instruction.pattern()).or_insert(0) += 1;
+
+        let counted = match self.stacks.get_mut(&self.frames[..]) {
+            Some(count) => {
+                *count += 1;
+                true
+            },
+            None => false,
+        };
+        if !counted {
+            self.stacks.insert(self.frames.clone(), 1);
+        }
+
+        let current = self.frames[self.frames.len() - 1];
+        self.subroutines.entry(current).or_default().self_instructions += 1;
+        for (depth, frame) in self.frames.iter().enumerate() {
+            // Recursive calls only count once
+            if !self.frames[..depth].contains(frame) {
+                self.subroutines.entry(*frame).or_default().total_instructions += 1;
+            }
+        }
+
+        match instruction {
+            Instruction::Call(addr) => {
+                let frame = Frame::Subroutine(addr);
+                self.subroutines.entry(frame).or_default().calls += 1;
+                self.frames.push(frame);
+            },
+            Instruction::Ret if self.frames.len() > 1 => {
+                self.frames.pop();
+            },
+            _ => {},
+        }
+
+        let depth = sp as usize + 1;
+        self.frames.truncate(depth);
+        while self.frames.len() < depth {
+            self.frames.push(Frame::Unknown);
+        }
+    }
+
+    // A human readable report: the hottest addresses, instruction kinds and
+    // subroutines, most executed first. ram is used to disassemble the
+    // addresses.
+    pub fn write_report<W: Write>(&self, writer: &mut W, ram: &[u8]) -> io::Result<()> {
+        let total = self.instructions;
+        writeln!(writer, "Profile of {} instructions", total)?;
+
+        writeln!(writer, "\nBy address:")?;
+        writeln!(writer, "{:>12} {:>6}  instruction", "count", "%")?;
+        for (addr, count) in sorted_by_count(&self.by_addr) {
+            let text = match DisasmLine::at(ram, addr as usize) {
+                Some(line) => line.to_string(),
+                None => format!("{:04X}", addr),
+            };
+            writeln!(writer, "{:12} {:6.2}  {}", count, percent(count, total), text)?;
+        }
+
+        writeln!(writer, "\nBy instruction:")?;
+        writeln!(writer, "{:>12} {:>6}  opcode", "count", "%")?;
+        for (pattern, count) in sorted_by_count(&self.by_pattern) {
+            writeln!(writer, "{:12} {:6.2}  {}", count, percent(count, total), pattern)?;
+        }
+
+        writeln!(writer, "\nBy subroutine:")?;
+        writeln!(writer, "{:>8} {:>12} {:>6} {:>12} {:>6}  subroutine", "calls", "self", "%", "total", "%")?;
+        let mut subroutines: Vec<(&Frame, &SubroutineStats)> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.total_instructions.cmp(&a.1.total_instructions).then(a.0.cmp(b.0)));
+        for (frame, stats) in subroutines {
+            writeln!(writer, "{:8} {:12} {:6.2} {:12} {:6.2}  {}",
+                     stats.calls,
+                     stats.self_instructions, percent(stats.self_instructions, total),
+                     stats.total_instructions, percent(stats.total_instructions, total),
+                     frame)?;
+        }
+        Ok(())
+    }
+
+    // One line per call stack in the folded format flame graph tools read:
+    // the frames from the bottom up separated by semicolons, then the count
+    pub fn write_folded<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut stacks: Vec<(&Vec<Frame>, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+        for (frames, count) in stacks {
+            let names: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+            writeln!(writer, "{} {}", names.join(";"), count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+// Highest count first, ties in key order so reports are stable
+fn sorted_by_count<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut sorted: Vec<(K, u64)> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    sorted
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 }
+}
@@ -0,0 +1,139 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+
+// Behaviour that differs between the interpreters Chip 8 programs were
+// written for. Programs written for one interpreter often misbehave on
+// another, so these are selectable rather than hard-coded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx rather than shifting Vx in place
+    pub shift_uses_vy: bool,
+
+    // Fx55/Fx65 leave I pointing just past the last register transferred
+    pub load_store_increments_i: bool,
+
+    // Bnnn jumps to nnn + Vx, where x is the top nibble of nnn, instead of nnn + V0
+    pub jump_uses_vx: bool,
+
+    // Sprites are cut off at the edge of the screen instead of wrapping around
+    pub clip_sprites: bool,
+
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub logic_resets_vf: bool,
+
+    // Dxyn waits for the next frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+
+    // Fx0A finishes when the key is released rather than when it's pressed
+    pub key_wait_release: bool,
+}
+
+pub const PRESET_NAMES: [&'static str; 5] = ["legacy", "vip", "chip48", "schip", "xochip"];
+
+impl Quirks {
+    // What this emulator always did before the quirks were selectable, which
+    // matches none of the original interpreters exactly
+    pub fn legacy() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+
+    // The original COSMAC VIP interpreter
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            logic_resets_vf: true,
+            display_wait: true,
+            key_wait_release: true,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1, which kept the CHIP-48 behaviour
+    pub fn schip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    // One byte per quirk, as stored in save states and movies
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let quirks = [
+            self.shift_uses_vy,
+            self.load_store_increments_i,
+            self.jump_uses_vx,
+            self.clip_sprites,
+            self.logic_resets_vf,
+            self.display_wait,
+            self.key_wait_release,
+        ];
+        for quirk in quirks.iter() {
+            writer.write_u8(*quirk as u8)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_state<R: Read>(reader: &mut R) -> io::Result<Quirks> {
+        Ok(Quirks {
+            shift_uses_vy: reader.read_u8()? != 0,
+            load_store_increments_i: reader.read_u8()? != 0,
+            jump_uses_vx: reader.read_u8()? != 0,
+            clip_sprites: reader.read_u8()? != 0,
+            logic_resets_vf: reader.read_u8()? != 0,
+            display_wait: reader.read_u8()? != 0,
+            key_wait_release: reader.read_u8()? != 0,
+        })
+    }
+
+    // Look up a preset by one of the names in PRESET_NAMES
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "legacy" => Some(Quirks::legacy()),
+            "vip" => Some(Quirks::vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::schip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::legacy()
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+// Recent machine snapshots, newest last, for running the game backwards. The
+// newest is the frame on screen, the rest are the frames that can be stepped
+// back to. Only the newest snapshot is kept whole. Every older one is stored
+// as the run length encoded XOR against the snapshot after it, which is
+// mostly zeros since little changes from one frame to the next.
+pub struct RewindBuffer {
+    capacity: usize,
+
+    newest: Option<Vec<u8>>,
+
+    deltas: VecDeque<Delta>,
+}
+
+struct Delta {
+    // Length of the older snapshot, in case it differs from the newer one
+    len: usize,
+
+    data: Vec<u8>,
+}
+
+impl RewindBuffer {
+    // Keep up to capacity frames to step back to, the oldest is dropped after
+    // that
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            capacity: capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Number of frames that can be stepped back to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // Bytes used by all the snapshots
+    pub fn memory_used(&self) -> usize {
+        let newest = self.newest.as_ref().map_or(0, |snapshot| snapshot.len());
+        newest + self.deltas.iter().map(|delta| delta.data.len()).sum::<usize>()
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(Delta {
+                len: newest.len(),
+                data: compress(&newest, &snapshot),
+            });
+            while self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+    }
+
+    // Drop the newest snapshot, the frame on screen, and return the one before
+    // it
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        let newest = self.newest.take().unwrap_or_default();
+        let older = decompress(&delta, &newest);
+        self.newest = Some(older.clone());
+        Some(older)
+    }
+}
+
+// Encode older XOR newer as pairs of runs: a varint count of zero bytes,
+// then a varint count of literal bytes followed by the bytes themselves
+fn compress(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let len = if older.len() > newer.len() { older.len() } else { newer.len() };
+    let xored: Vec<u8> = (0..len)
+        .map(|i| older.get(i).unwrap_or(&0) ^ newer.get(i).unwrap_or(&0))
+        .collect();
+
+    let mut data = Vec::new();
+    let mut i = 0;
+    while i < len {
+        let zeros_start = i;
+        while i < len && xored[i] == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < len && xored[i] != 0 {
+            i += 1;
+        }
+        write_varint(&mut data, literal_start - zeros_start);
+        write_varint(&mut data, i - literal_start);
+        data.extend_from_slice(&xored[literal_start..i]);
+    }
+    data
+}
+
+fn decompress(delta: &Delta, newer: &[u8]) -> Vec<u8> {
+    let mut older = newer.to_vec();
+    older.resize(delta.len, 0);
+
+    let data = &delta.data[..];
+    let mut pos = 0;
+    let mut i = 0;
+    while pos < data.len() {
+        i += read_varint(data, &mut pos);
+        let literals = read_varint(data, &mut pos);
+        for byte in &data[pos..pos + literals] {
+            if i < older.len() {
+                older[i] ^= *byte;
+            }
+            i += 1;
+        }
+        pos += literals;
+    }
+    older
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,121 @@
+use rand;
+
+// Used instead of zero, which xorshift can never leave
+const NONZERO_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// How Cxkk comes up with its random byte
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RngMode {
+    // xorshift64*, well distributed and independent of timing
+    #[default]
+    Xorshift,
+
+    // A counter advanced every frame, mixed with the previous result and a
+    // byte of the memory below the program. This follows the idea of the
+    // COSMAC VIP interpreter, which mixed its frame counter with bytes of
+    // its own code, but that memory holds our fonts rather than the VIP's
+    // interpreter so the numbers are not the ones a VIP would give. What it
+    // does keep is that results depend on when the program asks and are
+    // noticeably less random.
+    Counter,
+}
+
+pub const RNG_MODE_NAMES: [&'static str; 2] = ["xorshift", "counter"];
+
+impl RngMode {
+    pub fn from_name(name: &str) -> Option<RngMode> {
+        match name {
+            "xorshift" => Some(RngMode::Xorshift),
+            "counter" => Some(RngMode::Counter),
+            _ => None,
+        }
+    }
+
+    // The inverse of mode as u8
+    pub fn from_index(index: u8) -> Option<RngMode> {
+        match index {
+            0 => Some(RngMode::Xorshift),
+            1 => Some(RngMode::Counter),
+            _ => None,
+        }
+    }
+}
+
+// The generator behind Cxkk. It is owned by the machine rather than drawn
+// from the thread's generator so runs can be repeated from a seed, and its
+// state can be saved and restored along with everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rng {
+    mode: RngMode,
+
+    // The xorshift state, or for the counter mode the frame counter in the
+    // low byte and the previous result in the byte above it
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng::with_mode(RngMode::Xorshift, seed)
+    }
+
+    pub fn with_mode(mode: RngMode, seed: u64) -> Rng {
+        let mut rng = Rng { mode: mode, state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+
+    // Seeded from the operating system, a different sequence every run
+    pub fn from_entropy() -> Rng {
+        Rng::new(random_seed())
+    }
+
+    pub fn mode(&self) -> RngMode {
+        self.mode
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u64) {
+        self.state = match self.mode {
+            RngMode::Xorshift if state == 0 => NONZERO_SEED,
+            RngMode::Xorshift => state,
+            RngMode::Counter => state & 0xFFFF,
+        };
+    }
+
+    // Called once per 60 Hz frame
+    pub fn tick(&mut self) {
+        if self.mode == RngMode::Counter {
+            let counter = (self.state as u8).wrapping_add(1);
+            self.state = (self.state & 0xFF00) | counter as u64;
+        }
+    }
+
+    // The next random byte. interpreter_area is the memory below the program
+    // the counter mode reads from, the xorshift mode ignores it.
+    pub fn next_u8(&mut self, interpreter_area: &[u8]) -> u8 {
+        match self.mode {
+            RngMode::Xorshift => {
+                self.state ^= self.state >> 12;
+                self.state ^= self.state << 25;
+                self.state ^= self.state >> 27;
+                (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            },
+            RngMode::Counter => {
+                let counter = (self.state as u8).wrapping_add(1);
+                let byte = interpreter_area.get(counter as usize).cloned().unwrap_or(0);
+                let value = ((self.state >> 8) as u8).wrapping_add(byte).wrapping_add(counter);
+                self.state = (value as u64) << 8 | counter as u64;
+                value
+            },
+        }
+    }
+}
+
+// A seed from the operating system, for when a run should be reproducible
+// later but no seed was given
+pub fn random_seed() -> u64 {
+    rand::random()
+}
@@ -0,0 +1,56 @@
+use std::error;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Save state files start with MAGIC followed by VERSION as a big endian u16
+pub const MAGIC: &'static [u8; 4] = b"C8ST";
+
+// Bump whenever anything written by Chip8::save_state changes layout
+pub const VERSION: u16 = 3;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+
+    // Not a save state file at all
+    BadMagic,
+
+    // Written by a different version of the emulator
+    UnsupportedVersion(u16),
+
+    // A field holds a value the machine can never be in
+    Invalid(&'static str),
+
+    // Slots were used before Chip8::set_save_path
+    NoSavePath,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::Io(ref err) => write!(f, "{}", err),
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) =>
+                write!(f, "save state version {} is not supported, expected {}", version, VERSION),
+            SaveStateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+            SaveStateError::NoSavePath => write!(f, "no save path set"),
+        }
+    }
+}
+
+impl error::Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(err: io::Error) -> SaveStateError {
+        SaveStateError::Io(err)
+    }
+}
+
+// The file holding the given slot for a ROM, e.g. pong.ch8.ss1
+pub fn slot_path<P: AsRef<Path>>(rom_path: P, slot: u8) -> PathBuf {
+    let mut path = OsString::from(rom_path.as_ref());
+    path.push(format!(".ss{}", slot));
+    PathBuf::from(path)
+}
@@ -0,0 +1,473 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use sdl2;
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::{Event, WindowEventId};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::video::FullscreenType;
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioSpecDesired;
+
+use rust_chip8::display::{Display, LORES_WIDTH, LORES_HEIGHT, HIRES_WIDTH, HIRES_HEIGHT};
+use rust_chip8::audio::{AudioPattern, PATTERN_BITS};
+use rust_chip8::platform::{VideoSink, Beeper, InputSource, HostCommand};
+use rust_chip8::SaveStateError;
+use controller::{ControllerMap, Direction};
+use display_settings::{DisplaySettings, Palette, Persistence, Rgb};
+use keymap::KeyMap;
+
+struct PatternWave {
+    pattern: AudioPattern,
+    // Pattern bits advanced per output sample
+    bits_per_sample: f32,
+    sample_rate: f32,
+    position: f32,
+    volume: f32
+}
+
+impl PatternWave {
+    fn set_pattern(&mut self, pattern: &AudioPattern) {
+        self.pattern = *pattern;
+        self.bits_per_sample = pattern.bit_rate() / self.sample_rate;
+    }
+}
+
+impl AudioCallback for PatternWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        //Play the pattern one bit at a time, looping
+        for x in out.iter_mut() {
+            *x = if self.pattern.bit(self.position as usize) {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.position = (self.position + self.bits_per_sample) % PATTERN_BITS as f32;
+        }
+    }
+}
+
+pub struct SdlPlatform {
+    // renderer
+    renderer: sdl2::render::Renderer<'static>,
+    palette: Palette,
+    fullscreen: bool,
+
+    // The last frame presented, drawn again when the window changes size
+    frame: Display,
+
+    persistence: Persistence,
+    decay: f32,
+
+    // The colour each pixel is showing when persistence is on, indexed like
+    // the display's pixels. It follows the frames a little behind them.
+    glow: Vec<[f32; 3]>,
+
+    // The frame before the last one, for blending
+    previous: Display,
+
+    // audio
+    audio_device: sdl2::audio::AudioDevice<PatternWave>,
+
+    // events
+    event_pump: sdl2::EventPump,
+
+    // Save state and rewind requests, waiting for take_commands
+    commands: Vec<HostCommand>,
+
+    keymap: KeyMap,
+    controller_map: ControllerMap,
+
+    // None if SDL couldn't start its controller support
+    controller_subsystem: Option<GameControllerSubsystem>,
+
+    // Open until they're unplugged
+    controllers: Vec<GameController>,
+
+    // Mapped inputs currently down, so a CHIP-8 key with several inputs
+    // stays pressed until all of them are released
+    held: HashSet<Input>,
+}
+
+// Something that can press a CHIP-8 key. Controllers are told apart by
+// their SDL instance id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Input {
+    Key(Keycode),
+    Button(i32, Button),
+    Axis(i32, Axis, Direction),
+}
+
+impl SdlPlatform {
+    pub fn new(keymap: KeyMap, controller_map: ControllerMap, display: DisplaySettings) -> SdlPlatform {
+        let sdl_context = sdl2::init().unwrap();
+        let video = sdl_context.video().unwrap();
+
+        let mut window = video.window("Chip 8", LORES_WIDTH as u32 * display.scale, LORES_HEIGHT as u32 * display.scale);
+        window.position_centered().opengl().resizable();
+        if display.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().unwrap();
+
+        let mut renderer = window.renderer()
+            .accelerated()
+            .build().unwrap();
+
+        let (r, g, b) = display.palette.colors[0];
+        renderer.set_draw_color(Color::RGB(r, g, b));
+        renderer.clear();
+        renderer.present();
+
+        let audio_system = sdl_context.audio().unwrap();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_system.open_playback(None, &desired_spec, |spec| {
+            let mut wave = PatternWave {
+                pattern: AudioPattern::new(),
+                bits_per_sample: 0.0,
+                sample_rate: spec.freq as f32,
+                position: 0.0,
+                volume: 0.25,
+            };
+            wave.set_pattern(&AudioPattern::new());
+            wave
+        }).unwrap();
+
+        // Controllers already plugged in are announced with
+        // ControllerDeviceAdded events like ones plugged in later
+        let controller_subsystem = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(err) => {
+                eprintln!("Controllers are not available: {}", err);
+                None
+            }
+        };
+
+        let event_pump = sdl_context.event_pump().unwrap();
+
+        SdlPlatform {
+            renderer: renderer,
+            palette: display.palette,
+            fullscreen: display.fullscreen,
+            frame: Display::new(),
+            persistence: display.persistence,
+            decay: display.decay,
+            glow: vec![to_f32(display.palette.colors[0]); HIRES_WIDTH * HIRES_HEIGHT],
+            previous: Display::new(),
+            audio_device: device,
+            event_pump: event_pump,
+            commands: Vec::new(),
+            keymap: keymap,
+            controller_map: controller_map,
+            controller_subsystem: controller_subsystem,
+            controllers: Vec::new(),
+            held: HashSet::new(),
+        }
+    }
+
+    // Draw the last frame as large as it fits the window in whole pixels,
+    // centred with black bars around it
+    fn render(&mut self) {
+        let (width, height) = (self.frame.width() as u32, self.frame.height() as u32);
+        let (window_width, window_height) = self.renderer.output_size().unwrap();
+        let size = cmp::max(1, cmp::min(window_width / width, window_height / height));
+        let left = (window_width as i32 - (width * size) as i32) / 2;
+        let top = (window_height as i32 - (height * size) as i32) / 2;
+
+        let background = self.palette.colors[0];
+        let mut rects: HashMap<Rgb, Vec<Rect>> = HashMap::new();
+        for x in 0..width {
+            for y in 0..height {
+                let color = self.shown_color(x as usize, y as usize);
+                if color != background {
+                    rects.entry(color).or_default()
+                        .push(Rect::new(left + (x * size) as i32, top + (y * size) as i32, size, size));
+                }
+            }
+        }
+
+        self.renderer.set_draw_color(Color::RGB(0, 0, 0));
+        self.renderer.clear();
+        let (r, g, b) = background;
+        self.renderer.set_draw_color(Color::RGB(r, g, b));
+        self.renderer.fill_rect(Rect::new(left, top, width * size, height * size)).unwrap();
+        for (&(r, g, b), rects) in rects.iter() {
+            self.renderer.set_draw_color(Color::RGB(r, g, b));
+            self.renderer.fill_rects(&rects[..]).unwrap();
+        }
+        self.renderer.present();
+    }
+
+    fn shown_color(&self, x: usize, y: usize) -> Rgb {
+        if self.persistence == Persistence::Off {
+            return self.palette.colors[self.frame.color(x, y) as usize];
+        }
+        let glow = self.glow[y * HIRES_WIDTH + x];
+        (glow[0].round() as u8, glow[1].round() as u8, glow[2].round() as u8)
+    }
+
+    // Move the glow on by a frame, returning true if any pixel changed
+    fn update_glow(&mut self) -> bool {
+        if self.frame.is_hires() != self.previous.is_hires() {
+            // The pixels are laid out differently, start again from nothing
+            let background = to_f32(self.palette.colors[0]);
+            for glow in self.glow.iter_mut() {
+                *glow = background;
+            }
+            self.previous = self.frame;
+        }
+
+        let mut changed = false;
+        for y in 0..self.frame.height() {
+            for x in 0..self.frame.width() {
+                let color = self.frame.color(x, y);
+                let target = to_f32(self.palette.colors[color as usize]);
+                let glow = self.glow[y * HIRES_WIDTH + x];
+                let shown = match self.persistence {
+                    // Pixels light up at once but take a while to go out
+                    Persistence::Fade if color == 0 => fade(glow, target, self.decay),
+                    Persistence::Blend => {
+                        let previous = to_f32(self.palette.colors[self.previous.color(x, y) as usize]);
+                        fade(previous, target, 0.5)
+                    },
+                    _ => target,
+                };
+                if shown != glow {
+                    self.glow[y * HIRES_WIDTH + x] = shown;
+                    changed = true;
+                }
+            }
+        }
+        self.previous = self.frame;
+        changed
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = !self.fullscreen;
+        let mode = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        let result = match self.renderer.window_mut() {
+            Some(window) => window.set_fullscreen(mode),
+            None => return,
+        };
+        match result {
+            Ok(()) => self.fullscreen = fullscreen,
+            Err(err) => eprintln!("Could not switch fullscreen: {}", err),
+        }
+    }
+
+    fn key_for(&self, input: Input) -> Option<u8> {
+        match input {
+            Input::Key(keycode) => self.keymap.key(keycode),
+            Input::Button(_, button) => self.controller_map.button(button),
+            Input::Axis(_, axis, direction) => self.controller_map.axis(axis, direction),
+        }
+    }
+
+    fn is_key_held(&self, key: u8) -> bool {
+        self.held.iter().any(|&input| self.key_for(input) == Some(key))
+    }
+
+    // Follow what an event presses and releases, and controllers coming and
+    // going. Returns the CHIP-8 keys it pressed or released, with whether
+    // each is now held.
+    fn update_inputs(&mut self, event: &Event) -> Vec<(u8, bool)> {
+        let mut pressed = Vec::new();
+        let mut released = Vec::new();
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), .. } => pressed.push(Input::Key(keycode)),
+            Event::KeyUp { keycode: Some(keycode), .. } => released.push(Input::Key(keycode)),
+            Event::ControllerButtonDown { which, button, .. } => pressed.push(Input::Button(which, button)),
+            Event::ControllerButtonUp { which, button, .. } => released.push(Input::Button(which, button)),
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let pushed = self.controller_map.direction(value);
+                for &direction in &[Direction::Negative, Direction::Positive] {
+                    if pushed == Some(direction) {
+                        pressed.push(Input::Axis(which, axis, direction));
+                    } else {
+                        released.push(Input::Axis(which, axis, direction));
+                    }
+                }
+            },
+            Event::ControllerDeviceAdded { which, .. } => self.open_controller(which as u32),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|controller| controller.attached());
+                println!("Controller disconnected");
+                released.extend(self.held.iter().cloned().filter(|&input| match input {
+                    Input::Button(id, _) | Input::Axis(id, _, _) => id == which,
+                    Input::Key(_) => false,
+                }));
+            },
+            _ => {}
+        }
+
+        let mut keys: Vec<u8> = pressed.iter().chain(released.iter())
+            .filter_map(|&input| self.key_for(input))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        let before: Vec<bool> = keys.iter().map(|&key| self.is_key_held(key)).collect();
+
+        for input in released {
+            self.held.remove(&input);
+        }
+        for input in pressed {
+            if self.key_for(input).is_some() {
+                self.held.insert(input);
+            }
+        }
+
+        keys.into_iter().zip(before)
+            .map(|(key, was_held)| (key, was_held, self.is_key_held(key)))
+            .filter(|&(_, was_held, held)| was_held != held)
+            .map(|(key, _, held)| (key, held))
+            .collect()
+    }
+
+    fn open_controller(&mut self, index: u32) {
+        let controller = match self.controller_subsystem {
+            Some(ref subsystem) if subsystem.is_game_controller(index) => subsystem.open(index),
+            _ => return,
+        };
+        match controller {
+            Ok(controller) => {
+                println!("Controller connected: {}", controller.name());
+                self.controllers.push(controller);
+            },
+            Err(err) => eprintln!("Could not open controller {}: {}", index, err),
+        }
+    }
+}
+
+impl VideoSink for SdlPlatform {
+    fn present(&mut self, display_state: &Display) {
+        self.frame = *display_state;
+        // With persistence on, refresh shows it along with the glow
+        if self.persistence == Persistence::Off {
+            self.render();
+        }
+    }
+
+    fn refresh(&mut self) {
+        if self.persistence != Persistence::Off && self.update_glow() {
+            self.render();
+        }
+    }
+}
+
+impl Beeper for SdlPlatform {
+    fn start_beep(&mut self) {
+        self.audio_device.resume();
+    }
+
+    fn stop_beep(&mut self) {
+        self.audio_device.pause();
+    }
+
+    fn set_pattern(&mut self, pattern: &AudioPattern) {
+        self.audio_device.lock().set_pattern(pattern);
+    }
+}
+
+impl InputSource for SdlPlatform {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        let mut quit = false;
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            use sdl2::event::Event::*;
+            use sdl2::keyboard::Keycode::*;
+
+            for (key, held) in self.update_inputs(&event) {
+                key_state[key as usize] = held;
+            }
+
+            match event {
+                Quit { .. } => quit = true,
+
+                KeyDown { keycode, keymod, repeat, .. } => match keycode {
+                    Some(Escape) => quit = true,
+                    Some(Backspace) if !repeat => self.commands.push(HostCommand::Rewind(true)),
+                    Some(F11) if !repeat => self.toggle_fullscreen(),
+                    Some(F12) if !repeat => self.commands.push(HostCommand::Break),
+                    // F1-F9 load a save state slot, with shift they save to it
+                    Some(keycode) => if let Some(slot) = slot_from_keycode(keycode) {
+                        if !repeat {
+                            let shift = keymod.intersects(sdl2::keyboard::LSHIFTMOD | sdl2::keyboard::RSHIFTMOD);
+                            self.commands.push(if shift {
+                                HostCommand::SaveState(slot)
+                            } else {
+                                HostCommand::LoadState(slot)
+                            });
+                        }
+                    },
+                    None => {}
+                },
+
+                KeyUp { keycode: Some(Backspace), .. } => self.commands.push(HostCommand::Rewind(false)),
+
+                Window { win_event_id: WindowEventId::SizeChanged, .. } |
+                Window { win_event_id: WindowEventId::Exposed, .. } => self.render(),
+
+                _ => {}
+            }
+        }
+        quit
+    }
+
+    fn take_commands(&mut self) -> Vec<HostCommand> {
+        self.commands.drain(..).collect()
+    }
+
+    fn command_finished(&mut self, command: HostCommand, result: Result<Option<PathBuf>, SaveStateError>) {
+        match (command, result) {
+            (HostCommand::SaveState(_), Ok(Some(path))) => println!("Saved state to {}", path.display()),
+            (HostCommand::SaveState(slot), Err(err)) => eprintln!("Could not save state to slot {}: {}", slot, err),
+            (HostCommand::LoadState(_), Ok(Some(path))) => println!("Loaded state from {}", path.display()),
+            (HostCommand::LoadState(slot), Err(err)) => eprintln!("Could not load state from slot {}: {}", slot, err),
+            _ => {}
+        }
+    }
+}
+
+fn slot_from_keycode(keycode: Keycode) -> Option<u8> {
+    use sdl2::keyboard::Keycode::*;
+    match keycode {
+        F1 => Some(1),
+        F2 => Some(2),
+        F3 => Some(3),
+        F4 => Some(4),
+        F5 => Some(5),
+        F6 => Some(6),
+        F7 => Some(7),
+        F8 => Some(8),
+        F9 => Some(9),
+        _ => None,
+    }
+}
+
+fn to_f32(color: Rgb) -> [f32; 3] {
+    [color.0 as f32, color.1 as f32, color.2 as f32]
+}
+
+// Move from one colour a fraction of the way to another, arriving once the
+// steps get too small to see
+fn fade(from: [f32; 3], to: [f32; 3], fraction: f32) -> [f32; 3] {
+    let mut faded = to;
+    for (faded, &from) in faded.iter_mut().zip(from.iter()) {
+        let step = (*faded - from) * fraction;
+        if step.abs() >= 0.5 {
+            *faded = from + step;
+        }
+    }
+    faded
+}
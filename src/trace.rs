@@ -0,0 +1,284 @@
+use std::io::{self, Write};
+
+use cpu::CPU;
+use disasm::DisasmLine;
+
+// Writes a line for every instruction executed: the cycle count, PC, opcode
+// and disassembly, then V0-VF, I, SP, DT and ST as they were before it ran.
+//
+//         12 0204 A300 LD I, 300            V:05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:0 DT:00 ST:00
+pub struct Tracer {
+    writer: Box<dyn Write>,
+
+    // Instructions executed since tracing started, logged or not
+    cycles: u64,
+
+    // Inclusive PC ranges to log, everything is logged when empty
+    ranges: Vec<(u16, u16)>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(writer: W) -> Tracer {
+        Tracer {
+            writer: Box::new(writer),
+            cycles: 0,
+            ranges: Vec::new(),
+        }
+    }
+
+    // Only log instructions from start to end inclusive, can be called more
+    // than once to log several ranges
+    pub fn add_range(&mut self, start: u16, end: u16) {
+        self.ranges.push((start, end));
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Log the instruction the CPU is about to execute
+    pub fn trace(&mut self, cpu: &CPU) -> io::Result<()> {
+        let pc = cpu.reg_pc();
+        let cycle = self.cycles;
+        self.cycles += 1;
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|&(start, end)| pc >= start && pc <= end) {
+            return Ok(());
+        }
+
+        writeln!(self.writer, "{}", format_line(cpu, cycle))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// The line written for the instruction at PC, without a line break
+pub fn format_line(cpu: &CPU, cycle: u64) -> String {
+    let pc = cpu.reg_pc();
+    let (opcode, text) = match DisasmLine::at(cpu.interconnect().ram(), pc as usize) {
+        Some(DisasmLine { opcode, instruction: Some(instruction), .. }) => (opcode, instruction.to_string()),
+        Some(DisasmLine { opcode, .. }) => (opcode, format!("DW {:04X}", opcode)),
+        None => (0, "??".to_string()),
+    };
+    let regs: Vec<String> = cpu.reg_gpr().iter().map(|r| format!("{:02X}", r)).collect();
+    format!("{:10} {:04X} {:04X} {:<20} V:{} I:{:04X} SP:{:X} DT:{:02X} ST:{:02X}",
+            cycle, pc, opcode, text, regs.join(" "),
+            cpu.reg_i(), cpu.reg_sp(), cpu.reg_dt(), cpu.reg_st())
+}
+
+// The lines Tracer writes, as a TraceFormat template
+pub const TRACE_FORMAT: &'static str = "{cycle} {pc} {op} {*}V:{v} I:{i} SP:{sp} DT:{dt} ST:{st}";
+
+// The machine state one line of a trace describes. Fields the trace doesn't
+// have are None.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TraceRecord {
+    pub pc: Option<u16>,
+    pub opcode: Option<u16>,
+    pub regs: [Option<u8>; 16],
+    pub i: Option<u16>,
+    pub sp: Option<u8>,
+    pub dt: Option<u8>,
+    pub st: Option<u8>,
+
+    // A byte of memory as it was after the instruction ran, for traces that
+    // log what instructions store
+    pub memory: Option<(u16, u8)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Cycle,
+    Pc,
+    Opcode,
+    Reg(usize),
+    Regs,
+    I,
+    Sp,
+    Dt,
+    St,
+    MemAddr,
+    MemValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Space,
+    Field(Field),
+    Skip,
+}
+
+// How the lines of a trace are laid out, so traces from other emulators can
+// be read. A template is literal text with these placeholders:
+//
+//     {pc} {op} {i} {sp} {dt} {st}   registers, in hex
+//     {v0} to {vf}                   a single V register
+//     {v}                            all 16 V registers, separated by spaces or commas
+//     {maddr} {mval}                 a byte of memory after the instruction
+//     {cycle}                        a decimal number that is read and ignored
+//     {*}                            any text up to what follows
+//
+// Whitespace matches any amount of whitespace, including none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFormat {
+    tokens: Vec<Token>,
+}
+
+impl TraceFormat {
+    pub fn parse(template: &str) -> Result<TraceFormat, String> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            if c != '{' && !c.is_whitespace() {
+                literal.push(c);
+                continue;
+            }
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(literal.clone()));
+                literal.clear();
+            }
+            if c.is_whitespace() {
+                if tokens.last() != Some(&Token::Space) {
+                    tokens.push(Token::Space);
+                }
+                continue;
+            }
+
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(format!("{{{} is missing its closing brace", name)),
+                }
+            }
+            let token = match name.as_ref() {
+                "*" => Token::Skip,
+                "cycle" => Token::Field(Field::Cycle),
+                "pc" => Token::Field(Field::Pc),
+                "op" => Token::Field(Field::Opcode),
+                "v" => Token::Field(Field::Regs),
+                "i" => Token::Field(Field::I),
+                "sp" => Token::Field(Field::Sp),
+                "dt" => Token::Field(Field::Dt),
+                "st" => Token::Field(Field::St),
+                "maddr" => Token::Field(Field::MemAddr),
+                "mval" => Token::Field(Field::MemValue),
+                _ if name.len() == 2 && name.starts_with('v') => match usize::from_str_radix(&name[1..], 16) {
+                    Ok(x) => Token::Field(Field::Reg(x)),
+                    Err(_) => return Err(format!("unknown field {{{}}}", name)),
+                },
+                _ => return Err(format!("unknown field {{{}}}", name)),
+            };
+            tokens.push(token);
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        Ok(TraceFormat { tokens: tokens })
+    }
+
+    // The record on this line, None if the line doesn't fit the format.
+    // Anything after the end of the template is ignored.
+    pub fn read_line(&self, line: &str) -> Option<TraceRecord> {
+        let mut record = TraceRecord::default();
+        let mut memory = (None, None);
+        if !match_tokens(&self.tokens, line.trim(), &mut record, &mut memory) {
+            return None;
+        }
+        if let (Some(addr), Some(value)) = memory {
+            record.memory = Some((addr, value));
+        }
+        Some(record)
+    }
+}
+
+impl Default for TraceFormat {
+    fn default() -> TraceFormat {
+        TraceFormat::parse(TRACE_FORMAT).unwrap()
+    }
+}
+
+fn match_tokens(tokens: &[Token], input: &str, record: &mut TraceRecord, memory: &mut (Option<u16>, Option<u8>)) -> bool {
+    let token = match tokens.first() {
+        Some(token) => token,
+        None => return true,
+    };
+    let rest = &tokens[1..];
+    match *token {
+        Token::Literal(ref literal) => {
+            input.starts_with(literal.as_str()) && match_tokens(rest, &input[literal.len()..], record, memory)
+        },
+        Token::Space => match_tokens(rest, input.trim_start(), record, memory),
+        Token::Skip => {
+            // As little as possible, so the rest of the template gets the most
+            for (i, _) in input.char_indices().chain(Some((input.len(), ' '))) {
+                let mut attempt = *record;
+                let mut attempt_memory = *memory;
+                if match_tokens(rest, &input[i..], &mut attempt, &mut attempt_memory) {
+                    *record = attempt;
+                    *memory = attempt_memory;
+                    return true;
+                }
+            }
+            false
+        },
+        Token::Field(Field::Regs) => {
+            let mut input = input;
+            for x in 0..16 {
+                if x > 0 {
+                    input = input.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+                }
+                let (value, after) = match read_number(input, 16) {
+                    Some((value, after)) if value <= 0xFF => (value, after),
+                    _ => return false,
+                };
+                record.regs[x] = Some(value as u8);
+                input = after;
+            }
+            match_tokens(rest, input, record, memory)
+        },
+        Token::Field(field) => {
+            let radix = if field == Field::Cycle { 10 } else { 16 };
+            let (value, after) = match read_number(input, radix) {
+                Some(number) => number,
+                None => return false,
+            };
+            let fits = match field {
+                Field::Cycle => true,
+                Field::Pc | Field::Opcode | Field::I | Field::MemAddr => value <= 0xFFFF,
+                _ => value <= 0xFF,
+            };
+            if !fits {
+                return false;
+            }
+            match field {
+                Field::Cycle | Field::Regs => {},
+                Field::Pc => record.pc = Some(value as u16),
+                Field::Opcode => record.opcode = Some(value as u16),
+                Field::Reg(x) => record.regs[x] = Some(value as u8),
+                Field::I => record.i = Some(value as u16),
+                Field::Sp => record.sp = Some(value as u8),
+                Field::Dt => record.dt = Some(value as u8),
+                Field::St => record.st = Some(value as u8),
+                Field::MemAddr => memory.0 = Some(value as u16),
+                Field::MemValue => memory.1 = Some(value as u8),
+            }
+            match_tokens(rest, after, record, memory)
+        },
+    }
+}
+
+// The longest run of digits at the start of input, allowing a 0x prefix
+// on hex, and what follows it
+fn read_number(input: &str, radix: u32) -> Option<(u64, &str)> {
+    let input = if radix == 16 && (input.starts_with("0x") || input.starts_with("0X")) { &input[2..] } else { input };
+    let len = input.find(|c: char| !c.is_digit(radix)).unwrap_or(input.len());
+    if len == 0 {
+        return None;
+    }
+    u64::from_str_radix(&input[..len], radix).ok().map(|value| (value, &input[len..]))
+}
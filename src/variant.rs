@@ -0,0 +1,52 @@
+use quirks::Quirks;
+use interconnect::{RAM_SIZE, CLASSIC_RAM_SIZE};
+
+// The interpreter whose instruction set is emulated. Each variant is a
+// superset of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Variant {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+pub const VARIANT_NAMES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "chip8" => Some(Variant::Chip8),
+            "schip" => Some(Variant::SuperChip),
+            "xochip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    // The inverse of variant as u8
+    pub fn from_index(index: u8) -> Option<Variant> {
+        match index {
+            0 => Some(Variant::Chip8),
+            1 => Some(Variant::SuperChip),
+            2 => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    // The quirks programs for this variant usually expect
+    pub fn default_quirks(&self) -> Quirks {
+        match *self {
+            Variant::Chip8 => Quirks::legacy(),
+            Variant::SuperChip => Quirks::schip(),
+            Variant::XoChip => Quirks::xochip(),
+        }
+    }
+
+    // Bytes of RAM programs can address
+    pub fn memory_size(&self) -> usize {
+        match *self {
+            Variant::XoChip => RAM_SIZE,
+            _ => CLASSIC_RAM_SIZE,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+extern crate rust_chip8;
+
+use rust_chip8::{decode, DecodeError, Instruction, Variant};
+use rust_chip8::Instruction::*;
+
+#[test]
+fn decodes_every_instruction() {
+    let cases: &[(u16, Instruction)] = &[
+        (0x0000, Halt),
+        (0x0123, Sys(0x123)),
+        (0x00E0, Cls),
+        (0x00EE, Ret),
+        (0x1ABC, Jp(0xABC)),
+        (0x2ABC, Call(0xABC)),
+        (0x31FF, SeByte(1, 0xFF)),
+        (0x42AA, SneByte(2, 0xAA)),
+        (0x5340, SeReg(3, 4)),
+        (0x6512, LdByte(5, 0x12)),
+        (0x7634, AddByte(6, 0x34)),
+        (0x8780, LdReg(7, 8)),
+        (0x8781, Or(7, 8)),
+        (0x8782, And(7, 8)),
+        (0x8783, Xor(7, 8)),
+        (0x8784, AddReg(7, 8)),
+        (0x8785, Sub(7, 8)),
+        (0x8786, Shr(7, 8)),
+        (0x8787, Subn(7, 8)),
+        (0x878E, Shl(7, 8)),
+        (0x9AB0, SneReg(0xA, 0xB)),
+        (0xA123, LdI(0x123)),
+        (0xB456, JpV0(0x456)),
+        (0xCD0F, Rnd(0xD, 0x0F)),
+        (0xDEF5, Drw(0xE, 0xF, 5)),
+        (0xE19E, Skp(1)),
+        (0xE2A1, Sknp(2)),
+        (0xF307, LdVxDt(3)),
+        (0xF40A, LdVxK(4)),
+        (0xF515, LdDtVx(5)),
+        (0xF618, LdStVx(6)),
+        (0xF71E, AddIVx(7)),
+        (0xF829, LdFVx(8)),
+        (0xF933, LdBVx(9)),
+        (0xFA55, LdIVx(0xA)),
+        (0xFB65, LdVxI(0xB)),
+        (0x00C4, ScrollDown(4)),
+        (0x00FB, ScrollRight),
+        (0x00FC, ScrollLeft),
+        (0x00FD, Exit),
+        (0x00FE, Lores),
+        (0x00FF, Hires),
+        (0xFC30, LdHfVx(0xC)),
+        (0xFD75, LdRVx(0xD)),
+        (0xFE85, LdVxR(0xE)),
+        (0x00D2, ScrollUp(2)),
+        (0x5122, SaveRange(1, 2)),
+        (0x5213, LoadRange(2, 1)),
+        (0xF201, Plane(2)),
+        (0xF002, Audio),
+        (0xF43A, Pitch(4)),
+    ];
+
+    for &(opcode, instruction) in cases {
+        assert_eq!(decode(opcode, 0), Ok(instruction), "opcode {:04X}", opcode);
+    }
+}
+
+#[test]
+fn rejects_unknown_opcodes() {
+    for &opcode in &[0x5121, 0x8128, 0x812F, 0x9121, 0xE1FF, 0xF1FF, 0xF100] {
+        assert_eq!(decode(opcode, 0), Err(DecodeError { opcode: opcode }), "opcode {:04X}", opcode);
+    }
+}
+
+#[test]
+fn disassembles_in_cowgod_mnemonics() {
+    assert_eq!(decode(0xD125, 0).unwrap().to_string(), "DRW V1, V2, 5");
+    assert_eq!(decode(0xA2F0, 0).unwrap().to_string(), "LD I, 2F0");
+    assert_eq!(decode(0xF355, 0).unwrap().to_string(), "LD [I], V3");
+}
+
+#[test]
+fn instructions_know_their_variant() {
+    assert_eq!(decode(0x00E0, 0).unwrap().variant(), Variant::Chip8);
+    assert_eq!(decode(0xD120, 0).unwrap().variant(), Variant::Chip8);
+    assert_eq!(decode(0x00C1, 0).unwrap().variant(), Variant::SuperChip);
+    assert_eq!(decode(0x00FF, 0).unwrap().variant(), Variant::SuperChip);
+    assert_eq!(decode(0xF130, 0).unwrap().variant(), Variant::SuperChip);
+    assert_eq!(decode(0x00D1, 0).unwrap().variant(), Variant::XoChip);
+    assert_eq!(decode(0xF000, 0x1234).unwrap().variant(), Variant::XoChip);
+}
+
+#[test]
+fn long_load_takes_address_from_next_word() {
+    let instruction = decode(0xF000, 0xBEEF).unwrap();
+
+    assert_eq!(instruction, LdILong(0xBEEF));
+    assert_eq!(instruction.size(), 4);
+    assert_eq!(decode(0xA123, 0xBEEF).unwrap().size(), 2);
+}
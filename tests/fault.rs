@@ -0,0 +1,93 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, CpuFault, FaultKind, RunLimit, StepOutcome};
+
+// Run the program headless and return the fault it stopped on
+fn run_to_fault(rom: Vec<u8>) -> (Chip8, CpuFault) {
+    let mut chip8 = Chip8::new(rom);
+    let summary = chip8.run_headless(RunLimit::Frames(10));
+    assert!(summary.halted);
+    let fault = summary.fault.expect("program should have faulted");
+    assert_eq!(chip8.fault(), Some(fault));
+    (chip8, fault)
+}
+
+#[test]
+fn stack_overflow() {
+    // 200: CALL 200, forever
+    let (chip8, fault) = run_to_fault(vec![0x22, 0x00]);
+
+    assert_eq!(fault, CpuFault { pc: 0x200, opcode: 0x2200, kind: FaultKind::StackOverflow });
+    assert_eq!(chip8.reg_sp(), 16);
+    assert_eq!(chip8.reg_pc(), 0x200);
+}
+
+#[test]
+fn stack_underflow() {
+    let (chip8, fault) = run_to_fault(vec![
+        0x60, 0x01, // 200: LD V0, 01
+        0x00, 0xEE, // 202: RET
+    ]);
+
+    assert_eq!(fault, CpuFault { pc: 0x202, opcode: 0x00EE, kind: FaultKind::StackUnderflow });
+    assert_eq!(chip8.reg_sp(), 0);
+    assert_eq!(chip8.reg_pc(), 0x202);
+    assert_eq!(chip8.registers()[0], 0x01);
+}
+
+#[test]
+fn store_past_end_of_memory() {
+    let (chip8, fault) = run_to_fault(vec![
+        0xAF, 0xFF, // 200: LD I, FFF
+        0x60, 0xAA, // 202: LD V0, AA
+        0xF1, 0x55, // 204: LD [I], V1
+    ]);
+
+    assert_eq!(fault, CpuFault {
+        pc: 0x204,
+        opcode: 0xF155,
+        kind: FaultKind::MemoryOutOfBounds(0x1000),
+    });
+    // Nothing was written, not even the byte that did fit
+    assert_eq!(chip8.ram()[0xFFF], 0);
+}
+
+#[test]
+fn fetch_past_end_of_memory() {
+    // 200: JP FFF, which leaves only one byte of the next opcode in RAM
+    let (chip8, fault) = run_to_fault(vec![0x1F, 0xFF]);
+
+    assert_eq!(fault.pc, 0xFFF);
+    assert_eq!(fault.kind, FaultKind::MemoryOutOfBounds(0x1000));
+    assert_eq!(chip8.reg_pc(), 0xFFF);
+}
+
+#[test]
+fn invalid_opcode() {
+    let (chip8, fault) = run_to_fault(vec![
+        0x60, 0x01, // 200: LD V0, 01
+        0xE0, 0xFF, // 202: not an instruction
+    ]);
+
+    assert_eq!(fault, CpuFault { pc: 0x202, opcode: 0xE0FF, kind: FaultKind::InvalidOpcode });
+    assert_eq!(chip8.reg_pc(), 0x202);
+}
+
+#[test]
+fn step_records_fault_like_frames_do() {
+    let mut chip8 = Chip8::new(vec![0x00, 0xEE]);
+    let fault = CpuFault { pc: 0x200, opcode: 0x00EE, kind: FaultKind::StackUnderflow };
+
+    assert_eq!(chip8.step(), StepOutcome::Faulted(fault));
+    assert_eq!(chip8.fault(), Some(fault));
+    assert!(chip8.is_halted());
+
+    // A faulted machine won't run any more frames
+    let summary = chip8.run_headless(RunLimit::Frames(1));
+    assert_eq!(summary.instructions, 0);
+    assert_eq!(summary.fault, Some(fault));
+
+    chip8.load_rom(&[0x60, 0x01]).unwrap();
+    assert_eq!(chip8.fault(), None);
+    assert!(!chip8.is_halted());
+}
@@ -0,0 +1,93 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, RunLimit};
+
+// Draws a two row sprite at 10,5 then stops
+const DRAW_ROM: [u8; 14] = [
+    0x60, 0x0A, // 200: LD V0, 0A
+    0x61, 0x05, // 202: LD V1, 05
+    0xA2, 0x0C, // 204: LD I, 20C
+    0xD0, 0x12, // 206: DRW V0, V1, 2
+    0x00, 0x00, // 208: halt
+    0x00, 0x00,
+    0xF0, 0x81, // 20C: sprite
+];
+
+fn lit_pixels(chip8: &Chip8) -> Vec<(usize, usize)> {
+    let display = chip8.display_state();
+    (0..display.height())
+        .flat_map(|y| (0..display.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| display.pixel(x, y))
+        .collect()
+}
+
+#[test]
+fn draws_sprite_and_halts() {
+    let mut chip8 = Chip8::new(DRAW_ROM.to_vec());
+    let summary = chip8.run_headless(RunLimit::Frames(10));
+
+    assert!(summary.halted);
+    assert_eq!(summary.instructions, 4);
+    assert_eq!(chip8.registers()[0x0], 0x0A);
+    assert_eq!(chip8.registers()[0x1], 0x05);
+    assert_eq!(chip8.registers()[0xF], 0);
+    assert_eq!(chip8.reg_i(), 0x20C);
+    assert_eq!(lit_pixels(&chip8), vec![(10, 5), (11, 5), (12, 5), (13, 5), (10, 6), (17, 6)]);
+}
+
+#[test]
+fn timers_tick_once_a_frame() {
+    let rom = vec![
+        0x60, 0x3C, // 200: LD V0, 3C
+        0xF0, 0x15, // 202: LD DT, V0
+        0xF0, 0x18, // 204: LD ST, V0
+        0x12, 0x06, // 206: JP 206
+    ];
+    let mut chip8 = Chip8::new(rom);
+    let summary = chip8.run_headless(RunLimit::Frames(10));
+
+    assert!(!summary.halted);
+    assert_eq!(summary.frames, 10);
+    assert_eq!(summary.instructions, 10 * chip8.instructions_per_frame() as u64);
+    assert_eq!(chip8.reg_dt(), 0x3C - 10);
+    assert_eq!(chip8.reg_st(), 0x3C - 10);
+}
+
+#[test]
+fn instruction_limit_stops_mid_frame() {
+    let rom = vec![0x12, 0x00]; // 200: JP 200
+    let mut chip8 = Chip8::new(rom);
+    chip8.set_instructions_per_frame(10);
+    let summary = chip8.run_headless(RunLimit::Instructions(15));
+
+    assert!(!summary.halted);
+    assert_eq!(summary.instructions, 15);
+    assert_eq!(summary.frames, 2);
+}
+
+#[test]
+fn collision_sets_vf() {
+    let mut rom = DRAW_ROM.to_vec();
+    // Draw the sprite a second time over the first, erasing it
+    rom[8] = 0xD0;
+    rom[9] = 0x12;
+    let mut chip8 = Chip8::new(rom);
+    chip8.run_headless(RunLimit::Frames(10));
+
+    assert_eq!(chip8.registers()[0xF], 1);
+    assert!(lit_pixels(&chip8).is_empty());
+}
+
+#[test]
+fn load_rom_rejects_oversized_program() {
+    let mut chip8 = Chip8::new(DRAW_ROM.to_vec());
+    let program = vec![0xAA; 4096 - 0x200 + 1];
+
+    let err = chip8.load_rom(&program).unwrap_err();
+    assert_eq!(err.len, 4096 - 0x200 + 1);
+    assert_eq!(err.max, 4096 - 0x200);
+    // The previous program is left in place
+    assert_eq!(&chip8.ram()[0x200..0x20E], &DRAW_ROM[..]);
+
+    assert!(chip8.load_rom(&program[1..]).is_ok());
+}
@@ -0,0 +1,89 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, Display, Quirks, RunLimit};
+use rust_chip8::audio::AudioPattern;
+use rust_chip8::platform::{Beeper, InputSource, VideoSink};
+
+const ROM: [u8; 4] = [
+    0xF0, 0x0A, // 200: LD V0, K
+    0x00, 0x00, // 202: halt
+];
+
+// Holds down the keys listed for each frame, nothing once the list runs out
+struct ScriptedKeys {
+    frames: Vec<Vec<usize>>,
+    frame: usize,
+}
+
+impl VideoSink for ScriptedKeys {
+    fn present(&mut self, _display_state: &Display) {}
+}
+
+impl Beeper for ScriptedKeys {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn set_pattern(&mut self, _pattern: &AudioPattern) {}
+}
+
+impl InputSource for ScriptedKeys {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        *key_state = [false; 16];
+        if let Some(keys) = self.frames.get(self.frame) {
+            for &key in keys {
+                key_state[key] = true;
+            }
+        }
+        self.frame += 1;
+        false
+    }
+}
+
+// Runs ROM a frame at a time with the keys given, returning the frame Fx0A
+// finished on and the key it stored
+fn wait(quirks: Quirks, frames: Vec<Vec<usize>>) -> Option<(usize, u8)> {
+    let count = frames.len();
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.set_quirks(quirks);
+    let mut platform = ScriptedKeys { frames: frames, frame: 0 };
+    for frame in 0..count {
+        chip8.run_for(&mut platform, RunLimit::Frames(1));
+        if chip8.reg_pc() != 0x200 {
+            return Some((frame, chip8.registers()[0]));
+        }
+    }
+    None
+}
+
+#[test]
+fn press_then_release_across_frames() {
+    let frames = vec![vec![], vec![7], vec![7], vec![7], vec![], vec![]];
+    assert_eq!(wait(Quirks::vip(), frames.clone()), Some((4, 7)));
+    assert_eq!(wait(Quirks::legacy(), frames), Some((1, 7)));
+}
+
+#[test]
+fn keeps_waiting_while_the_key_is_held() {
+    assert_eq!(wait(Quirks::vip(), vec![vec![], vec![3], vec![3], vec![3]]), None);
+}
+
+#[test]
+fn keys_already_held_only_count_once_let_go() {
+    let frames = vec![vec![2], vec![2], vec![2, 9], vec![2], vec![2]];
+    assert_eq!(wait(Quirks::legacy(), frames.clone()), Some((2, 9)));
+    assert_eq!(wait(Quirks::vip(), frames), Some((3, 9)));
+
+    let frames = vec![vec![2], vec![], vec![2]];
+    assert_eq!(wait(Quirks::legacy(), frames), Some((2, 2)));
+}
+
+#[test]
+fn waiting_ends_the_frame() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    let mut platform = ScriptedKeys { frames: Vec::new(), frame: 0 };
+    let summary = chip8.run_for(&mut platform, RunLimit::Frames(3));
+    assert_eq!(summary.frames, 3);
+    assert_eq!(summary.instructions, 3);
+    assert!(!summary.halted);
+}
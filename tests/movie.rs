@@ -0,0 +1,119 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, Display, RunLimit, Variant};
+use rust_chip8::audio::AudioPattern;
+use rust_chip8::headless::HeadlessPlatform;
+use rust_chip8::movie::{Movie, MovieError, MoviePlayer, MovieRecorder};
+use rust_chip8::platform::{Beeper, InputSource, VideoSink};
+
+// Draws a dot somewhere random every time round while key 5 is held
+const ROM: [u8; 12] = [
+    0x62, 0x05, // 200: LD V2, 05
+    0xC0, 0x3F, // 202: RND V0, 3F
+    0xC1, 0x1F, // 204: RND V1, 1F
+    0xE2, 0xA1, // 206: SKNP V2
+    0xD0, 0x11, // 208: DRW V0, V1, 1
+    0x12, 0x02, // 20A: JP 202
+];
+
+const SEED: u64 = 0x5EED;
+
+// Holds key 5 down over a few stretches of frames
+struct ScriptedKeys {
+    frame: u64,
+}
+
+impl VideoSink for ScriptedKeys {
+    fn present(&mut self, _display_state: &Display) {}
+}
+
+impl Beeper for ScriptedKeys {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn set_pattern(&mut self, _pattern: &AudioPattern) {}
+}
+
+impl InputSource for ScriptedKeys {
+    fn poll(&mut self, key_state: &mut [bool; 16]) -> bool {
+        key_state[5] = (10..20).contains(&self.frame) || (30..35).contains(&self.frame);
+        self.frame += 1;
+        false
+    }
+}
+
+fn record(frames: u64) -> (Movie, Vec<u8>) {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.set_rng_seed(SEED);
+    let mut recorder = MovieRecorder::new(ScriptedKeys { frame: 0 }, Movie::new(&ROM, &chip8, SEED));
+    chip8.run_for(&mut recorder, RunLimit::Frames(frames));
+    let (_, movie) = recorder.finish();
+
+    let mut state = Vec::new();
+    chip8.save_state(&mut state).unwrap();
+    (movie, state)
+}
+
+#[test]
+fn playback_matches_recording() {
+    let (movie, recorded_state) = record(60);
+
+    let mut bytes = Vec::new();
+    movie.write(&mut bytes).unwrap();
+    let movie = Movie::read(&mut &bytes[..]).unwrap();
+
+    let mut chip8 = Chip8::new(Vec::new());
+    movie.configure(&ROM, &mut chip8).unwrap();
+    let mut player = MoviePlayer::new(HeadlessPlatform::new(), movie);
+    let limit = RunLimit::Frames(player.frames() + 1);
+    chip8.run_for(&mut player, limit);
+
+    assert_eq!(player.frames(), 60);
+    assert!(player.is_finished());
+    assert_eq!(player.desync(), None);
+    let mut state = Vec::new();
+    chip8.save_state(&mut state).unwrap();
+    assert_eq!(state, recorded_state);
+}
+
+#[test]
+fn different_random_numbers_desync() {
+    let (movie, _) = record(60);
+
+    let mut chip8 = Chip8::new(Vec::new());
+    movie.configure(&ROM, &mut chip8).unwrap();
+    chip8.set_rng_seed(SEED + 1);
+    let mut player = MoviePlayer::new(HeadlessPlatform::new(), movie);
+    chip8.run_for(&mut player, RunLimit::Frames(61));
+
+    // The first dot is drawn in frame 10
+    assert_eq!(player.desync(), Some(10));
+}
+
+#[test]
+fn other_rom_is_refused() {
+    let (movie, _) = record(1);
+
+    let mut chip8 = Chip8::new(Vec::new());
+    match movie.configure(&[0x12, 0x00], &mut chip8) {
+        Err(MovieError::RomMismatch) => {},
+        other => panic!("expected RomMismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn configure_sets_variant_before_loading() {
+    // Only fits in memory as XO-CHIP
+    let mut rom = vec![0; 0x1000];
+    rom[0] = 0x12;
+    let mut chip8 = Chip8::new(Vec::new());
+    chip8.set_variant(Variant::XoChip);
+    chip8.load_rom(&rom).unwrap();
+    let movie = Movie::new(&rom, &chip8, SEED);
+
+    let mut chip8 = Chip8::new(Vec::new());
+    movie.configure(&rom, &mut chip8).unwrap();
+    assert_eq!(chip8.variant(), Variant::XoChip);
+    assert_eq!(chip8.ram()[0x200], 0x12);
+}
@@ -0,0 +1,51 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, Display, RunLimit};
+use rust_chip8::audio::AudioPattern;
+use rust_chip8::platform::{Beeper, InputSource, VideoSink};
+
+// Counts what the machine asks of the screen
+#[derive(Default)]
+struct CountingSink {
+    presents: u32,
+    refreshes: u32,
+}
+
+impl VideoSink for CountingSink {
+    fn present(&mut self, _display_state: &Display) {
+        self.presents += 1;
+    }
+
+    fn refresh(&mut self) {
+        self.refreshes += 1;
+    }
+}
+
+impl Beeper for CountingSink {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn set_pattern(&mut self, _pattern: &AudioPattern) {}
+}
+
+impl InputSource for CountingSink {
+    fn poll(&mut self, _key_state: &mut [bool; 16]) -> bool {
+        false
+    }
+}
+
+#[test]
+fn refresh_every_frame_even_when_unchanged() {
+    let rom = vec![
+        0xD0, 0x05, // 200: DRW V0, V0, 5
+        0x12, 0x02, // 202: JP 202
+    ];
+    let mut chip8 = Chip8::new(rom);
+    let mut sink = CountingSink::default();
+    chip8.run_for(&mut sink, RunLimit::Frames(10));
+
+    // The sprite is shown on the first frame, then nothing changes
+    assert_eq!(sink.presents, 1);
+    assert_eq!(sink.refreshes, 10);
+}
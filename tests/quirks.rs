@@ -0,0 +1,170 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, Quirks, RunLimit};
+
+// Load the program with the given quirks and execute this many instructions
+fn run(rom: &[u8], quirks: Quirks, steps: usize) -> Chip8 {
+    let mut chip8 = Chip8::new(rom.to_vec());
+    chip8.set_quirks(quirks);
+    for _ in 0..steps {
+        chip8.step();
+    }
+    chip8
+}
+
+#[test]
+fn default_is_the_pre_quirks_behaviour() {
+    let quirks = Quirks::default();
+
+    assert_eq!(quirks, Quirks::legacy());
+    assert!(quirks.shift_uses_vy);
+    assert!(!quirks.load_store_increments_i);
+    assert!(!quirks.jump_uses_vx);
+    assert!(!quirks.clip_sprites);
+    assert!(!quirks.logic_resets_vf);
+    assert!(!quirks.display_wait);
+    assert_eq!(Chip8::new(Vec::new()).quirks(), quirks);
+}
+
+#[test]
+fn presets_by_name() {
+    assert_eq!(Quirks::from_name("legacy"), Some(Quirks::legacy()));
+    assert_eq!(Quirks::from_name("vip"), Some(Quirks::vip()));
+    assert_eq!(Quirks::from_name("schip"), Some(Quirks::chip48()));
+    assert_eq!(Quirks::from_name("xochip"), Some(Quirks::xochip()));
+    assert_eq!(Quirks::from_name("cosmac"), None);
+}
+
+#[test]
+fn shift_source() {
+    let shr = [
+        0x60, 0x02, // 200: LD V0, 02
+        0x61, 0x81, // 202: LD V1, 81
+        0x80, 0x16, // 204: SHR V0, V1
+    ];
+    let shl = [
+        0x60, 0x02, // 200: LD V0, 02
+        0x61, 0x81, // 202: LD V1, 81
+        0x80, 0x1E, // 204: SHL V0, V1
+    ];
+    let vy = Quirks { shift_uses_vy: true, ..Quirks::default() };
+    let vx = Quirks { shift_uses_vy: false, ..Quirks::default() };
+
+    let chip8 = run(&shr, vy, 3);
+    assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (0x40, 1));
+    let chip8 = run(&shr, vx, 3);
+    assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (0x01, 0));
+
+    let chip8 = run(&shl, vy, 3);
+    assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (0x02, 1));
+    let chip8 = run(&shl, vx, 3);
+    assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (0x04, 0));
+}
+
+#[test]
+fn load_store_increments_i() {
+    let rom = [
+        0xA3, 0x00, // 200: LD I, 300
+        0x60, 0x11, // 202: LD V0, 11
+        0x61, 0x22, // 204: LD V1, 22
+        0x62, 0x33, // 206: LD V2, 33
+        0xF2, 0x55, // 208: LD [I], V2
+        0xF2, 0x65, // 20A: LD V2, [I]
+    ];
+    let increments = Quirks { load_store_increments_i: true, ..Quirks::default() };
+    let leaves = Quirks { load_store_increments_i: false, ..Quirks::default() };
+
+    let chip8 = run(&rom, increments, 5);
+    assert_eq!(&chip8.ram()[0x300..0x303], &[0x11, 0x22, 0x33]);
+    assert_eq!(chip8.reg_i(), 0x303);
+    let chip8 = run(&rom, increments, 6);
+    assert_eq!(chip8.reg_i(), 0x306);
+
+    let chip8 = run(&rom, leaves, 5);
+    assert_eq!(&chip8.ram()[0x300..0x303], &[0x11, 0x22, 0x33]);
+    assert_eq!(chip8.reg_i(), 0x300);
+    let chip8 = run(&rom, leaves, 6);
+    assert_eq!(chip8.reg_i(), 0x300);
+    assert_eq!(&chip8.registers()[..3], &[0x11, 0x22, 0x33]);
+}
+
+#[test]
+fn jump_with_offset() {
+    let rom = [
+        0x60, 0x10, // 200: LD V0, 10
+        0x62, 0x20, // 202: LD V2, 20
+        0xB2, 0x34, // 204: JP V0, 234
+    ];
+
+    let chip8 = run(&rom, Quirks { jump_uses_vx: false, ..Quirks::default() }, 3);
+    assert_eq!(chip8.reg_pc(), 0x244);
+    let chip8 = run(&rom, Quirks { jump_uses_vx: true, ..Quirks::default() }, 3);
+    assert_eq!(chip8.reg_pc(), 0x254);
+}
+
+#[test]
+fn sprites_clip_or_wrap() {
+    let rom = [
+        0x60, 0x3E, // 200: LD V0, 62
+        0x61, 0x1F, // 202: LD V1, 31
+        0xA2, 0x0A, // 204: LD I, 20A
+        0xD0, 0x12, // 206: DRW V0, V1, 2
+        0x00, 0x00, // 208: halt
+        0xFF, 0xFF, // 20A: sprite
+    ];
+
+    let chip8 = run(&rom, Quirks { clip_sprites: false, ..Quirks::default() }, 4);
+    let display = chip8.display_state();
+    assert!(display.pixel(62, 31) && display.pixel(63, 31));
+    assert!(display.pixel(0, 31) && display.pixel(5, 31) && !display.pixel(6, 31));
+    assert!(display.pixel(62, 0) && display.pixel(0, 0) && display.pixel(5, 0));
+
+    let chip8 = run(&rom, Quirks { clip_sprites: true, ..Quirks::default() }, 4);
+    let display = chip8.display_state();
+    assert!(display.pixel(62, 31) && display.pixel(63, 31));
+    assert!(!display.pixel(0, 31) && !display.pixel(62, 0) && !display.pixel(0, 0));
+}
+
+#[test]
+fn logic_ops_reset_vf() {
+    for &op in &[0x1, 0x2, 0x3] {
+        let rom = [
+            0x6F, 0x01, // 200: LD VF, 01
+            0x60, 0x0C, // 202: LD V0, 0C
+            0x61, 0x0A, // 204: LD V1, 0A
+            0x80, 0x10 | op, // 206: OR/AND/XOR V0, V1
+        ];
+        let expected = match op {
+            0x1 => 0x0E,
+            0x2 => 0x08,
+            _ => 0x06,
+        };
+
+        let chip8 = run(&rom, Quirks { logic_resets_vf: true, ..Quirks::default() }, 4);
+        assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (expected, 0));
+        let chip8 = run(&rom, Quirks { logic_resets_vf: false, ..Quirks::default() }, 4);
+        assert_eq!((chip8.registers()[0], chip8.registers()[0xF]), (expected, 1));
+    }
+}
+
+#[test]
+fn display_wait_ends_the_frame() {
+    let rom = vec![
+        0xD0, 0x15, // 200: DRW V0, V1, 5
+        0x72, 0x01, // 202: ADD V2, 01
+        0x12, 0x00, // 204: JP 200
+    ];
+
+    let mut chip8 = Chip8::new(rom.clone());
+    chip8.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+    let summary = chip8.run_headless(RunLimit::Frames(1));
+    assert_eq!(summary.instructions, 1);
+    let summary = chip8.run_headless(RunLimit::Frames(1));
+    assert_eq!(summary.instructions, 3);
+    assert_eq!(chip8.registers()[2], 1);
+
+    let mut chip8 = Chip8::new(rom);
+    chip8.set_quirks(Quirks { display_wait: false, ..Quirks::default() });
+    let summary = chip8.run_headless(RunLimit::Frames(1));
+    assert_eq!(summary.instructions, chip8.instructions_per_frame() as u64);
+}
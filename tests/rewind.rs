@@ -0,0 +1,61 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, Quirks, RunLimit};
+use rust_chip8::headless::HeadlessPlatform;
+
+// Counts frames in V0, drawing a sprite to end each frame under the VIP
+// display wait
+const ROM: [u8; 6] = [
+    0x70, 0x01, // 200: ADD V0, 01
+    0xD1, 0x11, // 202: DRW V1, V1, 1
+    0x12, 0x00, // 204: JP 200
+];
+
+fn machine(rewind_frames: usize) -> Chip8 {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.set_quirks(Quirks::vip());
+    chip8.set_rewind_frames(rewind_frames);
+    chip8
+}
+
+#[test]
+fn first_rewind_goes_back_one_frame() {
+    let mut chip8 = machine(3);
+    let mut platform = HeadlessPlatform::new();
+    chip8.run_for(&mut platform, RunLimit::Frames(5));
+    assert_eq!(chip8.registers()[0], 5);
+    assert_eq!(chip8.rewind_buffer().unwrap().len(), 3);
+
+    // Every one of the 3 frames kept is a step back from what's shown
+    for frame in (2..5).rev() {
+        assert!(chip8.rewind_frame(&mut platform));
+        assert_eq!(chip8.registers()[0], frame);
+    }
+    assert!(!chip8.rewind_frame(&mut platform));
+    assert_eq!(chip8.registers()[0], 2);
+}
+
+#[test]
+fn running_on_after_a_rewind() {
+    let mut chip8 = machine(10);
+    let mut platform = HeadlessPlatform::new();
+    chip8.run_for(&mut platform, RunLimit::Frames(6));
+    chip8.rewind_frame(&mut platform);
+    chip8.rewind_frame(&mut platform);
+    assert_eq!(chip8.registers()[0], 4);
+
+    chip8.run_for(&mut platform, RunLimit::Frames(1));
+    assert_eq!(chip8.registers()[0], 5);
+    assert!(chip8.rewind_frame(&mut platform));
+    assert_eq!(chip8.registers()[0], 4);
+}
+
+#[test]
+fn rewind_off_by_default() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    let mut platform = HeadlessPlatform::new();
+    chip8.run_for(&mut platform, RunLimit::Frames(5));
+
+    assert!(chip8.rewind_buffer().is_none());
+    assert!(!chip8.rewind_frame(&mut platform));
+}
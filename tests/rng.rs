@@ -0,0 +1,70 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, RngMode, RunLimit};
+use rust_chip8::rng::Rng;
+
+// Fills memory from 300 with random bytes masked with kk
+fn random_bytes(mode: RngMode, seed: u64, kk: u8) -> Vec<u8> {
+    let rom = vec![
+        0xA3, 0x00, // 200: LD I, 300
+        0x61, 0x01, // 202: LD V1, 01
+        0xC0, kk,   // 204: RND V0, kk
+        0xF0, 0x55, // 206: LD [I], V0
+        0xF1, 0x1E, // 208: ADD I, V1
+        0x12, 0x04, // 20A: JP 204
+    ];
+    let mut chip8 = Chip8::new(rom);
+    chip8.set_rng_mode(mode);
+    chip8.set_rng_seed(seed);
+    chip8.run_headless(RunLimit::Frames(60));
+    chip8.ram()[0x300..0x340].to_vec()
+}
+
+#[test]
+fn same_seed_same_numbers() {
+    for &mode in &[RngMode::Xorshift, RngMode::Counter] {
+        assert_eq!(random_bytes(mode, 42, 0xFF), random_bytes(mode, 42, 0xFF));
+    }
+}
+
+#[test]
+fn different_seeds_differ() {
+    assert!(random_bytes(RngMode::Xorshift, 1, 0xFF) != random_bytes(RngMode::Xorshift, 2, 0xFF));
+}
+
+#[test]
+fn numbers_are_masked_and_spread_out() {
+    let bytes = random_bytes(RngMode::Xorshift, 7, 0x0F);
+    assert!(bytes.iter().all(|&byte| byte <= 0x0F));
+
+    let mut seen = [false; 16];
+    for &byte in &bytes {
+        seen[byte as usize] = true;
+    }
+    assert!(seen.iter().filter(|&&seen| seen).count() > 8);
+}
+
+#[test]
+fn xorshift_output_is_pinned() {
+    // Changing these changes every recorded movie, keep them fixed
+    let mut rng = Rng::new(1);
+    let bytes: Vec<u8> = (0..8).map(|_| rng.next_u8(&[])).collect();
+    assert_eq!(bytes, vec![0x47, 0xAB, 0xB9, 0x4D, 0x0E, 0xC8, 0xD0, 0xAC]);
+}
+
+#[test]
+fn counter_output_is_pinned() {
+    // 12 + area[35] + 35, then 36 and, after a frame, 38
+    let area: Vec<u8> = (0..0x200).map(|i| i as u8 ^ 0x5A).collect();
+    let mut rng = Rng::with_mode(RngMode::Counter, 0x1234);
+    let mut bytes = vec![rng.next_u8(&area), rng.next_u8(&area)];
+    rng.tick();
+    bytes.push(rng.next_u8(&area));
+    assert_eq!(bytes, vec![0xB6, 0x58, 0xF2]);
+}
+
+#[test]
+fn zero_seed_still_gives_numbers() {
+    let mut rng = Rng::new(0);
+    assert!((0..8).map(|_| rng.next_u8(&[])).any(|byte| byte != 0));
+}
@@ -0,0 +1,103 @@
+extern crate rust_chip8;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use rust_chip8::{Chip8, RunLimit, SaveStateError};
+use rust_chip8::platform::HostCommand;
+use rust_chip8::savestate;
+
+// Draws random bytes at random places through a subroutine, forever
+const ROM: [u8; 16] = [
+    0xA2, 0x0E, // 200: LD I, 20E
+    0x22, 0x06, // 202: CALL 206
+    0x12, 0x02, // 204: JP 202
+    0xC0, 0x3F, // 206: RND V0, 3F
+    0xC1, 0x1F, // 208: RND V1, 1F
+    0xD0, 0x11, // 20A: DRW V0, V1, 1
+    0x00, 0xEE, // 20C: RET
+    0xA5, 0x00, // 20E: sprite
+];
+
+fn state(chip8: &Chip8) -> Vec<u8> {
+    let mut state = Vec::new();
+    chip8.save_state(&mut state).unwrap();
+    state
+}
+
+#[test]
+fn load_resumes_where_save_left_off() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.run_headless(RunLimit::Frames(5));
+    let saved = state(&chip8);
+
+    chip8.run_headless(RunLimit::Frames(20));
+    let expected = state(&chip8);
+    let registers = chip8.registers().to_vec();
+    let (pc, i, sp) = (chip8.reg_pc(), chip8.reg_i(), chip8.reg_sp());
+
+    // Into a fresh machine, which has nothing in common with the first
+    let mut restored = Chip8::new(Vec::new());
+    restored.load_state(&mut &saved[..]).unwrap();
+    assert_eq!(state(&restored), saved);
+    restored.run_headless(RunLimit::Frames(20));
+
+    assert_eq!(restored.registers(), &registers[..]);
+    assert_eq!((restored.reg_pc(), restored.reg_i(), restored.reg_sp()), (pc, i, sp));
+    assert_eq!(state(&restored), expected);
+}
+
+#[test]
+fn rejected_state_leaves_machine_alone() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.run_headless(RunLimit::Frames(3));
+    let before = state(&chip8);
+
+    let mut bad_magic = before.clone();
+    bad_magic[0] = b'X';
+    match chip8.load_state(&mut &bad_magic[..]) {
+        Err(SaveStateError::BadMagic) => {},
+        other => panic!("expected BadMagic, got {:?}", other),
+    }
+
+    let mut bad_version = before.clone();
+    bad_version[5] = (savestate::VERSION + 1) as u8;
+    match chip8.load_state(&mut &bad_version[..]) {
+        Err(SaveStateError::UnsupportedVersion(version)) => assert_eq!(version, savestate::VERSION + 1),
+        other => panic!("expected UnsupportedVersion, got {:?}", other),
+    }
+
+    match chip8.load_state(&mut &before[..before.len() / 2]) {
+        Err(SaveStateError::Io(_)) => {},
+        other => panic!("expected Io, got {:?}", other),
+    }
+
+    assert_eq!(state(&chip8), before);
+}
+
+#[test]
+fn slot_commands_report_the_file_used() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    match chip8.handle_command(HostCommand::SaveState(1)) {
+        Err(SaveStateError::NoSavePath) => {},
+        other => panic!("expected NoSavePath, got {:?}", other),
+    }
+
+    let rom_path = env::temp_dir().join(format!("savestate-test-{}.ch8", process::id()));
+    chip8.set_save_path(&rom_path);
+    chip8.run_headless(RunLimit::Frames(3));
+    let saved = state(&chip8);
+
+    let path = chip8.handle_command(HostCommand::SaveState(4)).unwrap().unwrap();
+    assert_eq!(path, savestate::slot_path(&rom_path, 4));
+    chip8.run_headless(RunLimit::Frames(3));
+    assert_eq!(chip8.handle_command(HostCommand::LoadState(4)).unwrap(), Some(path.clone()));
+    assert_eq!(state(&chip8), saved);
+
+    fs::remove_file(&path).unwrap();
+    match chip8.handle_command(HostCommand::LoadState(4)) {
+        Err(SaveStateError::Io(_)) => {},
+        other => panic!("expected Io, got {:?}", other),
+    }
+}
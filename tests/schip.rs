@@ -0,0 +1,178 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, CpuFault, FaultKind, Instruction, StepOutcome, Variant};
+
+fn load(rom: &[u8], variant: Variant) -> Chip8 {
+    let mut chip8 = Chip8::new(Vec::new());
+    chip8.set_variant(variant);
+    chip8.load_rom(rom).unwrap();
+    chip8
+}
+
+fn run(rom: &[u8], variant: Variant, steps: usize) -> Chip8 {
+    let mut chip8 = load(rom, variant);
+    for _ in 0..steps {
+        chip8.step();
+    }
+    chip8
+}
+
+fn lit_pixels(chip8: &Chip8) -> Vec<(usize, usize)> {
+    let display = chip8.display_state();
+    (0..display.height())
+        .flat_map(|y| (0..display.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| display.pixel(x, y))
+        .collect()
+}
+
+// Draws a single pixel at 10,10
+const DOT: [u8; 10] = [
+    0x60, 0x0A, // 200: LD V0, 0A
+    0xA2, 0x08, // 202: LD I, 208
+    0xD0, 0x01, // 204: DRW V0, V0, 1
+    0x12, 0x06, // 206: JP 206
+    0x80, 0x00, // 208: sprite
+];
+
+fn with_dot(extra: &[u8]) -> Vec<u8> {
+    // Replace the final jump with the extra instructions
+    let mut rom = DOT[..6].to_vec();
+    rom.extend_from_slice(extra);
+    rom.extend_from_slice(&[0x00, 0x00]);
+    let sprite = rom.len();
+    rom.extend_from_slice(&[0x80, 0x00]);
+    rom[3] = (0x200 + sprite) as u8;
+    rom
+}
+
+#[test]
+fn scroll_down() {
+    let chip8 = run(&with_dot(&[0x00, 0xC3]), Variant::SuperChip, 4);
+    assert_eq!(lit_pixels(&chip8), vec![(10, 13)]);
+
+    // Scrolled off the bottom edge rather than wrapping
+    let chip8 = run(&with_dot(&[0x00, 0xCF, 0x00, 0xCF]), Variant::SuperChip, 5);
+    assert!(lit_pixels(&chip8).is_empty());
+}
+
+#[test]
+fn scroll_right_and_left() {
+    let chip8 = run(&with_dot(&[0x00, 0xFB]), Variant::SuperChip, 4);
+    assert_eq!(lit_pixels(&chip8), vec![(14, 10)]);
+
+    let chip8 = run(&with_dot(&[0x00, 0xFC]), Variant::SuperChip, 4);
+    assert_eq!(lit_pixels(&chip8), vec![(6, 10)]);
+
+    let chip8 = run(&with_dot(&[0x00, 0xFC, 0x00, 0xFC, 0x00, 0xFC]), Variant::SuperChip, 6);
+    assert!(lit_pixels(&chip8).is_empty());
+}
+
+#[test]
+fn exit_halts() {
+    let mut chip8 = load(&[0x00, 0xFD, 0x60, 0x01], Variant::SuperChip);
+
+    assert_eq!(chip8.step(), StepOutcome::Halted);
+    assert!(chip8.is_halted());
+    assert_eq!(chip8.step(), StepOutcome::Halted);
+    assert_eq!(chip8.registers()[0], 0);
+}
+
+#[test]
+fn resolution_switch_clears_screen() {
+    let mut chip8 = load(&with_dot(&[0x00, 0xFF, 0x00, 0xFE]), Variant::SuperChip);
+    for _ in 0..3 {
+        chip8.step();
+    }
+    assert!(!chip8.display_state().is_hires());
+    assert_eq!(lit_pixels(&chip8).len(), 1);
+
+    assert_eq!(chip8.step(), StepOutcome::Drew(Instruction::Hires));
+    assert!(chip8.display_state().is_hires());
+    assert_eq!((chip8.display_state().width(), chip8.display_state().height()), (128, 64));
+    assert!(lit_pixels(&chip8).is_empty());
+
+    assert_eq!(chip8.step(), StepOutcome::Drew(Instruction::Lores));
+    assert_eq!((chip8.display_state().width(), chip8.display_state().height()), (64, 32));
+}
+
+#[test]
+fn large_sprite() {
+    let mut rom = vec![
+        0x00, 0xFF, // 200: HIGH
+        0x60, 0x04, // 202: LD V0, 04
+        0xA2, 0x10, // 204: LD I, 210
+        0xD0, 0x00, // 206: DRW V0, V0, 0
+        0x00, 0x00, // 208: halt
+        0x00, 0x00,
+        0x00, 0x00,
+        0x00, 0x00,
+    ];
+    // 210: 16x16 sprite, the left column and the top row
+    rom.extend_from_slice(&[0xFF, 0xFF]);
+    for _ in 1..16 {
+        rom.extend_from_slice(&[0x80, 0x00]);
+    }
+
+    let chip8 = run(&rom, Variant::SuperChip, 4);
+    let mut expected: Vec<(usize, usize)> = (4..20).map(|x| (x, 4)).collect();
+    expected.extend((5..20).map(|y| (4, y)));
+    expected.sort_by_key(|&(x, y)| (y, x));
+    assert_eq!(lit_pixels(&chip8), expected);
+    assert_eq!(chip8.registers()[0xF], 0);
+
+    // Drawing it again erases it and reports the collision
+    rom[8] = 0xD0;
+    rom[9] = 0x00;
+    let chip8 = run(&rom, Variant::SuperChip, 5);
+    assert!(lit_pixels(&chip8).is_empty());
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn large_sprite_is_empty_in_chip8() {
+    let rom = [
+        0xA2, 0x04, // 200: LD I, 204
+        0xD0, 0x00, // 202: DRW V0, V0, 0
+        0xFF, 0xFF,
+    ];
+    let chip8 = run(&rom, Variant::Chip8, 2);
+    assert!(lit_pixels(&chip8).is_empty());
+}
+
+#[test]
+fn large_font_and_rpl_flags() {
+    let rom = [
+        0x60, 0x07, // 200: LD V0, 07
+        0x61, 0x42, // 202: LD V1, 42
+        0xF0, 0x30, // 204: LD HF, V0
+        0xF1, 0x75, // 206: LD R, V1
+        0x60, 0x00, // 208: LD V0, 00
+        0x61, 0x00, // 20A: LD V1, 00
+        0xF1, 0x85, // 20C: LD V1, R
+    ];
+    let chip8 = run(&rom, Variant::SuperChip, 7);
+
+    assert_eq!(chip8.reg_i(), 80 + 7 * 10);
+    assert_eq!(&chip8.registers()[..2], &[0x07, 0x42]);
+}
+
+#[test]
+fn schip_instructions_are_sys_calls_in_chip8() {
+    for &opcode in &[0x00C3u16, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF] {
+        let mut chip8 = load(&[(opcode >> 8) as u8, opcode as u8], Variant::Chip8);
+
+        assert_eq!(chip8.step(), StepOutcome::Executed(Instruction::Sys(opcode)));
+        assert_eq!(chip8.reg_pc(), opcode);
+        assert!(!chip8.display_state().is_hires());
+    }
+}
+
+#[test]
+fn schip_instructions_fault_in_chip8() {
+    for &opcode in &[0xF030u16, 0xF075, 0xF085] {
+        let mut chip8 = load(&[(opcode >> 8) as u8, opcode as u8], Variant::Chip8);
+        let fault = CpuFault { pc: 0x200, opcode: opcode, kind: FaultKind::InvalidOpcode };
+
+        assert_eq!(chip8.step(), StepOutcome::Faulted(fault));
+    }
+}
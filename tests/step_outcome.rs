@@ -0,0 +1,56 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, CpuFault, FaultKind, Instruction, StepOutcome};
+
+#[test]
+fn each_kind_of_outcome() {
+    let rom = vec![
+        0x60, 0x05, // 200: LD V0, 05
+        0xD0, 0x05, // 202: DRW V0, V0, 5
+        0xF0, 0x0A, // 204: LD V0, K
+        0x00, 0x00, // 206: halt
+    ];
+    let mut chip8 = Chip8::new(rom);
+
+    assert_eq!(chip8.step(), StepOutcome::Executed(Instruction::LdByte(0, 0x05)));
+    assert_eq!(chip8.step(), StepOutcome::Drew(Instruction::Drw(0, 0, 5)));
+    assert_eq!(chip8.step(), StepOutcome::WaitingForKey);
+    assert_eq!(chip8.step(), StepOutcome::WaitingForKey);
+    assert_eq!(chip8.reg_pc(), 0x204);
+}
+
+#[test]
+fn halted_machine_stays_halted() {
+    let mut chip8 = Chip8::new(vec![0x00, 0x00, 0x60, 0x01]);
+
+    assert_eq!(chip8.step(), StepOutcome::Halted);
+    assert_eq!(chip8.step(), StepOutcome::Halted);
+    assert_eq!(chip8.registers()[0], 0);
+
+    // Loading a program brings it back to life
+    chip8.load_rom(&[0x60, 0x01]).unwrap();
+    assert_eq!(chip8.step(), StepOutcome::Executed(Instruction::LdByte(0, 0x01)));
+    assert_eq!(chip8.registers()[0], 0x01);
+}
+
+#[test]
+fn step_leaves_timers_alone() {
+    let rom = vec![
+        0x60, 0x3C, // 200: LD V0, 3C
+        0xF0, 0x15, // 202: LD DT, V0
+        0x12, 0x04, // 204: JP 204
+    ];
+    let mut chip8 = Chip8::new(rom);
+    for _ in 0..100 {
+        chip8.step();
+    }
+    assert_eq!(chip8.reg_dt(), 0x3C);
+}
+
+#[test]
+fn undecodable_opcode_faults() {
+    // 5xy1 isn't an instruction
+    let mut chip8 = Chip8::new(vec![0x50, 0x01]);
+    let fault = CpuFault { pc: 0x200, opcode: 0x5001, kind: FaultKind::InvalidOpcode };
+    assert_eq!(chip8.step(), StepOutcome::Faulted(fault));
+}
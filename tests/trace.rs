@@ -0,0 +1,110 @@
+extern crate rust_chip8;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rust_chip8::{Chip8, RunLimit, Tracer};
+use rust_chip8::trace::TraceFormat;
+
+const ROM: [u8; 6] = [
+    0x60, 0x05, // 200: LD V0, 05
+    0xA3, 0x00, // 202: LD I, 300
+    0x12, 0x04, // 204: JP 204
+];
+
+// Keeps what was written where the test can still get at it
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Broken;
+
+impl Write for Broken {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run(tracer: Tracer, instructions: u64) {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.start_trace(tracer);
+    chip8.run_headless(RunLimit::Instructions(instructions));
+}
+
+#[test]
+fn logs_each_instruction_before_it_runs() {
+    let out = Shared::default();
+    run(Tracer::new(out.clone()), 3);
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("         0 0200 6005 LD V0, 05 "));
+    assert!(lines[1].contains("0202 A300 LD I, 300"));
+    assert!(lines[1].contains("V:05 00"));
+    assert!(lines[2].contains("I:0300"));
+}
+
+#[test]
+fn ranges_limit_what_is_logged() {
+    let out = Shared::default();
+    let mut tracer = Tracer::new(out.clone());
+    tracer.add_range(0x204, 0x204);
+    run(tracer, 5);
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    assert_eq!(text.lines().count(), 3);
+    assert!(text.lines().all(|line| line.contains(" 0204 1204 ")));
+}
+
+#[test]
+fn write_errors_stop_tracing_and_are_kept() {
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.start_trace(Tracer::new(Broken));
+    let summary = chip8.run_headless(RunLimit::Frames(2));
+    assert_eq!(summary.frames, 2);
+    assert!(chip8.stop_trace().is_none());
+    assert_eq!(chip8.take_trace_error().unwrap().to_string(), "disk full");
+    assert!(chip8.take_trace_error().is_none());
+}
+
+#[test]
+fn own_lines_read_back() {
+    let out = Shared::default();
+    run(Tracer::new(out.clone()), 2);
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    let record = TraceFormat::default().read_line(text.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(record.pc, Some(0x202));
+    assert_eq!(record.opcode, Some(0xA300));
+    assert_eq!(record.regs[0], Some(0x05));
+    assert_eq!(record.i, Some(0));
+    assert_eq!(record.memory, None);
+}
+
+#[test]
+fn foreign_formats() {
+    let format = TraceFormat::parse("{*}pc={pc} v=[{v}] i={i} [{maddr}]={mval}").unwrap();
+    let record = format.read_line("cpu: pc=0x0300 v=[1,2,3,4,5,6,7,8,9,a,b,c,d,e,f,10] i=0123 [0456]=ff").unwrap();
+    assert_eq!(record.pc, Some(0x300));
+    assert_eq!(record.regs[0xF], Some(0x10));
+    assert_eq!(record.i, Some(0x123));
+    assert_eq!(record.memory, Some((0x456, 0xFF)));
+    assert_eq!(record.opcode, None);
+
+    assert!(format.read_line("cpu: reset").is_none());
+    assert!(TraceFormat::parse("{pc").is_err());
+    assert!(TraceFormat::parse("{vg}").is_err());
+}
@@ -0,0 +1,104 @@
+extern crate rust_chip8;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+use rust_chip8::{Chip8, RunLimit, Tracer};
+
+const ROM: [u8; 8] = [
+    0x60, 0x05, // 200: LD V0, 05
+    0xA3, 0x00, // 202: LD I, 300
+    0x70, 0x01, // 204: ADD V0, 01
+    0x12, 0x04, // 206: JP 204
+];
+
+// How another emulator might log the same program, one line per instruction
+const THEIR_FORMAT: &'static str = "PC={pc} OP={op} V0={v0} I={i}";
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rust_chip8_{}_{}", process::id(), name))
+}
+
+// Runs tracecmp on our trace of the first instructions of ROM and the given
+// lines as theirs
+fn compare_with(name: &str, instructions: u64, theirs: &[&str], args: &[&str]) -> Output {
+    let our_path = temp_path(&format!("{}.ours", name));
+    let their_path = temp_path(&format!("{}.theirs", name));
+
+    // The same tracing --trace does
+    let mut chip8 = Chip8::new(ROM.to_vec());
+    chip8.start_trace(Tracer::new(BufWriter::new(File::create(&our_path).unwrap())));
+    chip8.run_headless(RunLimit::Instructions(instructions));
+    drop(chip8.stop_trace());
+    File::create(&their_path).and_then(|mut file| file.write_all(theirs.join("\n").as_bytes())).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tracecmp"))
+        .args(args)
+        .arg(&our_path)
+        .arg(&their_path)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&our_path);
+    let _ = fs::remove_file(&their_path);
+    output
+}
+
+#[test]
+fn matching_traces() {
+    let output = compare_with("match", 4, &[
+        "emulator v1.0 starting",
+        "PC=0200 OP=6005 V0=00 I=0000",
+        "PC=0202 OP=A300 V0=05 I=0000",
+        "PC=0204 OP=7001 V0=05 I=0300",
+        "PC=0206 OP=1204 V0=06 I=0300",
+    ], &["--format", THEIR_FORMAT]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert_eq!(stdout, "4 instructions match\n");
+}
+
+#[test]
+fn reports_the_first_difference_with_context() {
+    let output = compare_with("differ", 5, &[
+        "PC=0200 OP=6005 V0=00 I=0000",
+        "PC=0202 OP=A300 V0=05 I=0000",
+        "PC=0204 OP=7001 V0=05 I=0300",
+        "PC=0206 OP=1204 V0=07 I=0300",
+        "PC=0204 OP=7001 V0=06 I=0301",
+    ], &["--format", THEIR_FORMAT, "--context", "1"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("Traces diverge at instruction 3 (line 4 of "), "{}", stdout);
+    assert_eq!(lines[1], "    V0: ours 06, theirs 07");
+    assert_eq!(lines.len(), 7, "{}", stdout);
+    assert!(lines[3].starts_with("ours:   ") && lines[3].contains("0204 7001"), "{}", stdout);
+    assert_eq!(lines[4], "theirs: PC=0204 OP=7001 V0=05 I=0300");
+    assert_eq!(lines[6], "theirs: PC=0206 OP=1204 V0=07 I=0300");
+}
+
+#[test]
+fn ignored_fields_are_not_compared() {
+    let output = compare_with("ignore", 3, &[
+        "PC=0200 OP=6005 V0=00 I=0000",
+        "PC=0202 OP=A300 V0=05 I=0000",
+        "PC=0204 OP=7001 V0=05 I=0400",
+    ], &["--format", THEIR_FORMAT, "--ignore", "i"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn shorter_trace_is_reported() {
+    let output = compare_with("short", 4, &[
+        "PC=0200 OP=6005 V0=00 I=0000",
+        "PC=0202 OP=A300 V0=05 I=0000",
+    ], &["--format", "PC={pc} OP={op} V0={v0} I={i}"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.starts_with("2 instructions match, then "), "{}", stdout);
+    assert!(stdout.ends_with(".theirs ends\n"), "{}", stdout);
+}
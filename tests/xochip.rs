@@ -0,0 +1,171 @@
+extern crate rust_chip8;
+
+use rust_chip8::{Chip8, CpuFault, FaultKind, Instruction, Quirks, StepOutcome, Variant};
+
+fn load(rom: &[u8], variant: Variant) -> Chip8 {
+    let mut chip8 = Chip8::new(Vec::new());
+    chip8.set_variant(variant);
+    chip8.set_quirks(variant.default_quirks());
+    chip8.load_rom(rom).unwrap();
+    chip8
+}
+
+fn run(rom: &[u8], steps: usize) -> Chip8 {
+    let mut chip8 = load(rom, Variant::XoChip);
+    for _ in 0..steps {
+        chip8.step();
+    }
+    chip8
+}
+
+#[test]
+fn long_load_of_i() {
+    let mut chip8 = load(&[
+        0xF0, 0x00, 0xBE, 0xEF, // 200: LD I, BEEF
+        0x60, 0x01,             // 204: LD V0, 01
+    ], Variant::XoChip);
+
+    assert_eq!(chip8.step(), StepOutcome::Executed(Instruction::LdILong(0xBEEF)));
+    assert_eq!(chip8.reg_i(), 0xBEEF);
+    assert_eq!(chip8.reg_pc(), 0x204);
+    chip8.step();
+    assert_eq!(chip8.registers()[0], 0x01);
+}
+
+#[test]
+fn skips_step_over_long_load() {
+    let chip8 = run(&[
+        0x30, 0x00,             // 200: SE V0, 00
+        0xF0, 0x00, 0x12, 0x34, // 202: LD I, 1234
+        0x61, 0x01,             // 206: LD V1, 01
+    ], 2);
+
+    assert_eq!(chip8.reg_i(), 0);
+    assert_eq!(chip8.registers()[1], 0x01);
+    assert_eq!(chip8.reg_pc(), 0x208);
+}
+
+#[test]
+fn long_load_faults_before_xochip() {
+    for &variant in &[Variant::Chip8, Variant::SuperChip] {
+        let mut chip8 = load(&[0xF0, 0x00, 0x12, 0x34], variant);
+        let fault = CpuFault { pc: 0x200, opcode: 0xF000, kind: FaultKind::InvalidOpcode };
+        assert_eq!(chip8.step(), StepOutcome::Faulted(fault));
+    }
+}
+
+#[test]
+fn memory_is_64k() {
+    let chip8 = run(&[
+        0xF0, 0x00, 0xFF, 0xFE, // 200: LD I, FFFE
+        0x60, 0xAB,             // 204: LD V0, AB
+        0x61, 0xCD,             // 206: LD V1, CD
+        0xF1, 0x55,             // 208: LD [I], V1
+    ], 4);
+
+    assert_eq!(chip8.ram().len(), 0x10000);
+    assert_eq!(&chip8.ram()[0xFFFE..], &[0xAB, 0xCD]);
+    // I is incremented past the end and wraps around
+    assert_eq!(chip8.reg_i(), 0x0000);
+}
+
+#[test]
+fn rom_size_limit_follows_variant() {
+    let program = vec![0x12; 0x1000];
+
+    let mut chip8 = Chip8::new(Vec::new());
+    let err = chip8.load_rom(&program).unwrap_err();
+    assert_eq!(err.max, 0x1000 - 0x200);
+
+    chip8.set_variant(Variant::XoChip);
+    assert!(chip8.load_rom(&program).is_ok());
+    assert_eq!(chip8.ram()[0x11FF], 0x12);
+    assert_eq!(chip8.load_rom(&vec![0x12; 0x10000]).unwrap_err().max, 0x10000 - 0x200);
+}
+
+#[test]
+fn plane_selects_what_drawing_touches() {
+    let rom = [
+        0xF2, 0x01, // 200: PLANE 2
+        0xA2, 0x10, // 202: LD I, 210
+        0xD0, 0x01, // 204: DRW V0, V0, 1
+        0xF3, 0x01, // 206: PLANE 3
+        0x61, 0x08, // 208: LD V1, 08
+        0xD1, 0x01, // 20A: DRW V1, V0, 1
+        0x00, 0x00, // 20C: halt
+        0x00, 0x00,
+        0x80, 0xC0, // 210: sprite, one row per plane when drawing to both
+    ];
+
+    let chip8 = run(&rom, 3);
+    assert_eq!(chip8.display_state().planes(), 2);
+    assert_eq!(chip8.display_state().color(0, 0), 2);
+    assert_eq!(chip8.display_state().color(1, 0), 0);
+
+    let chip8 = run(&rom, 6);
+    assert_eq!(chip8.display_state().planes(), 3);
+    // Plane 1 takes the first byte, plane 2 the second
+    assert_eq!(chip8.display_state().color(8, 0), 3);
+    assert_eq!(chip8.display_state().color(9, 0), 2);
+    assert_eq!(chip8.display_state().color(0, 0), 2);
+}
+
+#[test]
+fn clear_only_touches_selected_planes() {
+    let chip8 = run(&[
+        0xF3, 0x01, // 200: PLANE 3
+        0xA2, 0x0C, // 202: LD I, 20C
+        0xD0, 0x01, // 204: DRW V0, V0, 1
+        0xF1, 0x01, // 206: PLANE 1
+        0x00, 0xE0, // 208: CLS
+        0x00, 0x00, // 20A: halt
+        0x80, 0x80, // 20C: sprite
+    ], 5);
+
+    assert_eq!(chip8.display_state().color(0, 0), 2);
+}
+
+#[test]
+fn save_and_load_register_ranges() {
+    let rom = [
+        0xA3, 0x00, // 200: LD I, 300
+        0x62, 0x22, // 202: LD V2, 22
+        0x63, 0x33, // 204: LD V3, 33
+        0x64, 0x44, // 206: LD V4, 44
+        0x52, 0x42, // 208: LD [I], V2-V4
+        0xA3, 0x10, // 20A: LD I, 310
+        0x54, 0x22, // 20C: LD [I], V4-V2
+        0xA3, 0x00, // 20E: LD I, 300
+        0x57, 0x93, // 210: LD V7-V9, [I]
+    ];
+
+    let chip8 = run(&rom, 5);
+    assert_eq!(&chip8.ram()[0x300..0x304], &[0x22, 0x33, 0x44, 0x00]);
+    assert_eq!(chip8.reg_i(), 0x300);
+
+    let chip8 = run(&rom, 7);
+    assert_eq!(&chip8.ram()[0x310..0x313], &[0x44, 0x33, 0x22]);
+    assert_eq!(chip8.reg_i(), 0x310);
+
+    let chip8 = run(&rom, 9);
+    assert_eq!(&chip8.registers()[7..10], &[0x22, 0x33, 0x44]);
+    assert_eq!(chip8.reg_i(), 0x300);
+}
+
+#[test]
+fn register_ranges_fault_past_end_of_memory() {
+    let mut chip8 = load(&[
+        0xF0, 0x00, 0xFF, 0xFF, // 200: LD I, FFFF
+        0x50, 0x12,             // 204: LD [I], V0-V1
+    ], Variant::XoChip);
+    chip8.step();
+
+    let fault = CpuFault { pc: 0x204, opcode: 0x5012, kind: FaultKind::MemoryOutOfBounds(0x10000) };
+    assert_eq!(chip8.step(), StepOutcome::Faulted(fault));
+    assert_eq!(chip8.ram()[0xFFFF], 0);
+}
+
+#[test]
+fn xochip_quirks_preset_is_the_variant_default() {
+    assert_eq!(Variant::XoChip.default_quirks(), Quirks::xochip());
+}